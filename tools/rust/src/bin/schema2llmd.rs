@@ -5,6 +5,7 @@
 
 use clap::Parser;
 use llmdc::config::Config;
+use llmdc::util::ellipsize_graphemes;
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
 use std::fs;
@@ -24,6 +25,14 @@ struct Cli {
     /// Config file path (auto-detect llmdc.config.json)
     #[arg(long)]
     config: Option<PathBuf>,
+
+    /// After generating, warn about required properties missing from an object's property list
+    #[arg(long)]
+    verify: bool,
+
+    /// With --verify, exit with an error when a required property is missing
+    #[arg(long, requires = "verify")]
+    strict: bool,
 }
 
 fn die(msg: &str) -> ! {
@@ -54,11 +63,17 @@ fn load_config(config_path: Option<&PathBuf>) -> Config {
 
 struct SchemaCtx {
     root: Value,
+    desc_max_len: usize,
+    desc_ellipsis: String,
 }
 
 impl SchemaCtx {
-    fn new(root: Value) -> Self {
-        Self { root }
+    fn new(root: Value, desc_max_len: usize, desc_ellipsis: &str) -> Self {
+        Self {
+            root,
+            desc_max_len,
+            desc_ellipsis: desc_ellipsis.to_string(),
+        }
     }
 
     fn definitions(&self) -> &serde_json::Map<String, Value> {
@@ -87,17 +102,15 @@ impl SchemaCtx {
         if node.is_null() {
             return vec![];
         }
+        let mut props: Vec<(String, &'a Value)> = vec![];
+        // Expand a ref only once; the node's own properties are still collected
         if let Some(r) = node.get("$ref").and_then(|v| v.as_str()) {
-            if visited.contains(r) {
-                return vec![];
-            }
-            visited.insert(r.to_string());
-            if let Some(resolved) = self.resolve_ref(r) {
-                return self.collect_properties(resolved, visited);
+            if visited.insert(r.to_string()) {
+                if let Some(resolved) = self.resolve_ref(r) {
+                    props.extend(self.collect_properties(resolved, visited));
+                }
             }
-            return vec![];
         }
-        let mut props: Vec<(String, &'a Value)> = vec![];
         if let Some(arr) = node.get("allOf").and_then(|v| v.as_array()) {
             for sub in arr {
                 let mut v = visited.clone();
@@ -125,6 +138,10 @@ impl SchemaCtx {
     }
 
     fn get_required(&self, node: &Value) -> HashSet<String> {
+        self.collect_required(node, &mut HashSet::new())
+    }
+
+    fn collect_required(&self, node: &Value, visited: &mut HashSet<String>) -> HashSet<String> {
         if node.is_null() {
             return HashSet::new();
         }
@@ -138,12 +155,14 @@ impl SchemaCtx {
         }
         if let Some(arr) = node.get("allOf").and_then(|v| v.as_array()) {
             for sub in arr {
-                req.extend(self.get_required(sub));
+                req.extend(self.collect_required(sub, visited));
             }
         }
         if let Some(r) = node.get("$ref").and_then(|v| v.as_str()) {
-            if let Some(resolved) = self.resolve_ref(r) {
-                req.extend(self.get_required(resolved));
+            if visited.insert(r.to_string()) {
+                if let Some(resolved) = self.resolve_ref(r) {
+                    req.extend(self.collect_required(resolved, visited));
+                }
             }
         }
         req
@@ -216,7 +235,7 @@ impl SchemaCtx {
             _ => {}
         }
         if let Some(r) = items.get("$ref").and_then(|v| v.as_str()) {
-            let name = r.split('/').last().unwrap_or("any");
+            let name = r.split('/').next_back().unwrap_or("any");
             return clean_def_name(name);
         }
         "any".into()
@@ -238,9 +257,7 @@ impl SchemaCtx {
                 LazyLock::new(|| regex::Regex::new(r"\[([^\]]+)\]\([^)]+\)").unwrap());
             desc = RE_LINK.replace_all(&desc, "$1").to_string();
         }
-        if desc.len() > 200 {
-            desc = format!("{}...", &desc[..197]);
-        }
+        desc = truncate_desc(&desc, self.desc_max_len, &self.desc_ellipsis);
         let mut parts = vec![];
         if !desc.is_empty() {
             parts.push(desc);
@@ -345,6 +362,14 @@ fn clean_def_name(name: &str) -> String {
     RE.replace(name, "").to_string()
 }
 
+/// Truncate to at most `max_len` graphemes (ellipsis included); 0 disables truncation.
+fn truncate_desc(desc: &str, max_len: usize, ellipsis: &str) -> String {
+    if max_len == 0 {
+        return desc.to_string();
+    }
+    ellipsize_graphemes(desc, max_len, ellipsis)
+}
+
 fn collapse_whitespace(s: &str) -> String {
     use std::sync::LazyLock;
     static RE: LazyLock<regex::Regex> = LazyLock::new(|| regex::Regex::new(r"\s+").unwrap());
@@ -470,6 +495,7 @@ struct Compressor {
     unit_num_regexes: Vec<(regex::Regex, String)>,
     unit_regexes: Vec<(regex::Regex, String)>,
     bool_compress: bool,
+    bool_map: HashMap<String, String>,
     stopwords: HashSet<String>,
     protect: HashSet<String>,
     re_alpha: regex::Regex,
@@ -483,7 +509,7 @@ impl Compressor {
 
         // Pre-compile phrase map regexes (longest-first)
         let mut phrases: Vec<(&String, &String)> = config.phrase_map.iter().collect();
-        phrases.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        phrases.sort_by_key(|e| std::cmp::Reverse(e.0.len()));
         let phrase_regexes: Vec<(regex::Regex, String)> = phrases
             .iter()
             .map(|(phrase, replacement)| {
@@ -494,7 +520,7 @@ impl Compressor {
 
         // Pre-compile unit regexes (longest-first)
         let mut unit_keys: Vec<(&String, &String)> = config.units.iter().collect();
-        unit_keys.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        unit_keys.sort_by_key(|e| std::cmp::Reverse(e.0.len()));
         let unit_num_regexes: Vec<(regex::Regex, String)> = unit_keys
             .iter()
             .map(|(unit, abbrev)| {
@@ -516,6 +542,7 @@ impl Compressor {
             unit_num_regexes,
             unit_regexes,
             bool_compress: config.bool_compress,
+            bool_map: llmdc::emit::bool_map(config),
             stopwords,
             protect,
             re_alpha: regex::Regex::new(r"[^a-z]").unwrap(),
@@ -541,17 +568,12 @@ impl Compressor {
 
         // Boolean compression
         if self.bool_compress {
-            let bool_map: HashMap<&str, &str> = [
-                ("yes", "Y"), ("no", "N"),
-                ("true", "T"), ("false", "F"),
-                ("enabled", "Y"), ("disabled", "N"),
-            ].into();
             let tokens: Vec<&str> = body.split_whitespace().collect();
             body = tokens
                 .iter()
                 .map(|t| {
                     let low = t.to_lowercase();
-                    match bool_map.get(low.as_str()) {
+                    match self.bool_map.get(low.as_str()) {
                         Some(v) => v.to_string(),
                         None => t.to_string(),
                     }
@@ -692,6 +714,33 @@ fn generate_llmd(ctx: &SchemaCtx, config: &Config) -> String {
     lines.join("\n") + "\n"
 }
 
+/// Required properties of each object definition that its `:Name.properties=`
+/// line in `output` does not list, as `(definition, property)` pairs.
+fn missing_required(ctx: &SchemaCtx, output: &str) -> Vec<(String, String)> {
+    let mut missing = Vec::new();
+    for (name, def_schema) in ctx.definitions() {
+        if !ctx.is_object_definition(name, def_schema) {
+            continue;
+        }
+        let clean = clean_def_name(name);
+        let prefix = format!(":{}.properties=", clean);
+        let listed: HashSet<&str> = output
+            .lines()
+            .filter_map(|l| l.strip_prefix(&prefix))
+            .flat_map(|list| list.split(", "))
+            .map(|p| p.trim_end_matches('!'))
+            .collect();
+        let mut required: Vec<String> = ctx.get_required(def_schema).into_iter().collect();
+        required.sort();
+        for prop in required {
+            if !listed.contains(prop.as_str()) {
+                missing.push((clean.clone(), prop));
+            }
+        }
+    }
+    missing
+}
+
 // ---------------------------------------------------------------------------
 // Main
 // ---------------------------------------------------------------------------
@@ -705,8 +754,8 @@ fn main() {
     let root: Value = serde_json::from_str(&content)
         .unwrap_or_else(|e| die(&format!("invalid JSON: {}", e)));
 
-    let ctx = SchemaCtx::new(root);
     let config = load_config(cli.config.as_ref());
+    let ctx = SchemaCtx::new(root, config.schema_desc_max_len, &config.schema_desc_ellipsis);
     let result = generate_llmd(&ctx, &config);
 
     if let Some(output_path) = &cli.output {
@@ -722,4 +771,123 @@ fn main() {
     } else {
         print!("{}", result);
     }
+
+    if cli.verify {
+        let missing = missing_required(&ctx, &result);
+        for (def, prop) in &missing {
+            eprintln!("warning: {}: required property '{}' is missing from the output", def, prop);
+        }
+        if cli.strict && !missing.is_empty() {
+            die(&format!("{} required propert(ies) missing from the output", missing.len()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use llmdc::util::grapheme_len;
+
+    fn tree_schema() -> Value {
+        serde_json::json!({
+            "definitions": {
+                "TreeNode": {
+                    "$ref": "#/definitions/TreeNode",
+                    "type": "object",
+                    "required": ["value"],
+                    "properties": {
+                        "value": {"type": "string"},
+                        "children": {
+                            "type": "array",
+                            "items": {"$ref": "#/definitions/TreeNode"}
+                        },
+                        "parent": {"$ref": "#/definitions/TreeNode"}
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_self_referential_ref_keeps_properties() {
+        let ctx = SchemaCtx::new(tree_schema(), 200, "...");
+        let def = &ctx.definitions()["TreeNode"];
+        let mut visited = HashSet::new();
+        let names: Vec<String> = ctx
+            .collect_properties(def, &mut visited)
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(names, vec!["value", "children", "parent"]);
+
+        let out = generate_llmd(&ctx, &Config::default());
+        assert!(out.contains(":TreeNode.properties=value!, children, parent\n"));
+        assert!(out.contains("-children (array of TreeNode): "));
+    }
+
+    #[test]
+    fn test_missing_required_flags_unhandled_combinator() {
+        // `id` is only defined under if/then, which collect_properties does not follow
+        let schema = serde_json::json!({
+            "definitions": {
+                "Widget": {
+                    "type": "object",
+                    "required": ["id", "name"],
+                    "properties": {"name": {"type": "string"}},
+                    "if": {"properties": {"name": {"const": "x"}}},
+                    "then": {"properties": {"id": {"type": "string"}}}
+                }
+            }
+        });
+        let ctx = SchemaCtx::new(schema, 200, "...");
+        let out = generate_llmd(&ctx, &Config::default());
+        assert!(out.contains(":Widget.properties=name!\n"));
+        assert_eq!(missing_required(&ctx, &out), vec![("Widget".to_string(), "id".to_string())]);
+
+        let ctx = SchemaCtx::new(tree_schema(), 200, "...");
+        assert!(missing_required(&ctx, &generate_llmd(&ctx, &Config::default())).is_empty());
+    }
+
+    #[test]
+    fn test_truncate_desc_multibyte_boundary() {
+        // 196 ASCII chars followed by multi-byte chars straddling the 197 cut
+        let desc = format!("{}é日本語ü", "a".repeat(196));
+        let out = truncate_desc(&desc, 200, "...");
+        assert_eq!(out, format!("{}é...", "a".repeat(196)));
+        assert_eq!(out.chars().count(), 200);
+    }
+
+    #[test]
+    fn test_truncate_desc_combining_accents() {
+        // Decomposed "é" is two chars; the cut must not separate the accent from its base
+        let desc = "e\u{301}".repeat(20);
+        let out = truncate_desc(&desc, 10, "...");
+        assert_eq!(out, format!("{}...", "e\u{301}".repeat(7)));
+        assert_eq!(grapheme_len(&out), 10);
+    }
+
+    #[test]
+    fn test_truncate_desc_limits() {
+        let desc = "日本語の説明".repeat(50);
+        assert_eq!(truncate_desc(&desc, 0, "..."), desc);
+        assert_eq!(truncate_desc("short", 200, "..."), "short");
+        assert_eq!(truncate_desc(&desc, 10, "..."), "日本語の説明日...");
+        assert_eq!(truncate_desc(&desc, 10, "…"), "日本語の説明日本語…");
+    }
+
+    #[test]
+    fn test_describe_property_uses_config_max_len() {
+        let ctx = SchemaCtx::new(Value::Null, 12, "...");
+        let prop = serde_json::json!({"description": "ünïcödé description text"});
+        assert_eq!(ctx.describe_property(&prop), "ünïcödé d...");
+    }
+
+    #[test]
+    fn test_compressor_custom_bool_map() {
+        let mut config = Config::default();
+        config.bool_map.insert("on".to_string(), "Y".to_string());
+        config.bool_map.insert("off".to_string(), "N".to_string());
+        let compressor = Compressor::new(&config);
+        assert_eq!(compressor.compress("Turns wrapping On or off, yes"), "Turns wrapping Y or off, yes");
+    }
 }
@@ -1,57 +1,279 @@
 use crate::ir::{CodeBlock, Stage1Result};
 use regex::Regex;
+use std::ops::Range;
 use std::sync::LazyLock;
 
 static RE_FENCE_OPEN: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^(`{3,})([a-zA-Z0-9_]*)\s*$").unwrap());
+static RE_YAML_KEY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:- )?[A-Za-z_][\w.-]*:(?:\s.*)?$").unwrap());
+static RE_INLINE_CODE_LINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^`([^`]+)`$").unwrap());
+static RE_ART_BORDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*\+(?:[-=]+\+)+\s*$").unwrap());
+static RE_ART_ROW: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*[|+].*[|+]\s*$").unwrap());
+static RE_LIST_MARKER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*(?:[-*+]|\d+\.)\s").unwrap());
+
+/// The backtick fence opening a code block on this line, if any.
+pub(crate) fn fence_open(line: &str) -> Option<String> {
+    RE_FENCE_OPEN.captures(line).map(|caps| caps[1].to_string())
+}
 
 pub fn stage1(lines: &[String]) -> Stage1Result {
+    extract(lines.iter().map(String::as_str), false)
+}
+
+/// Like [`stage1`], but over the normalized source as one string (lines joined
+/// by `\n`). Blocks keep a byte range into `source` instead of a copy of their
+/// content, so large embedded data is never duplicated; read it with
+/// [`CodeBlock::text`].
+pub fn stage1_spans(source: &str) -> Stage1Result {
+    extract(source.split('\n'), true)
+}
+
+fn extract<'a>(lines: impl Iterator<Item = &'a str>, spans: bool) -> Stage1Result {
     let mut blocks: Vec<CodeBlock> = Vec::new();
     let mut out: Vec<String> = Vec::new();
     let mut in_block = false;
     let mut lang = String::new();
-    let mut buf: Vec<String> = Vec::new();
+    let mut buf: Vec<&str> = Vec::new();
     let mut fence = String::new();
+    let mut source_lines: Vec<usize> = Vec::new();
+    let mut open_line = 0;
+    // Byte offset of each line in the `\n`-joined text, and the current block's content range
+    let mut offset = 0;
+    let mut content = 0..0;
+    let mut content_lines = 0;
+
+    let push_block = |blocks: &mut Vec<CodeBlock>, lang: &str, buf: &[&str], content: &Range<usize>| {
+        let idx = blocks.len();
+        blocks.push(CodeBlock {
+            index: idx,
+            lang: lang.to_string(),
+            content: if spans { String::new() } else { buf.join("\n") },
+            span: spans.then(|| content.clone()),
+        });
+        idx
+    };
 
-    for line in lines {
+    for (i, line) in lines.enumerate() {
+        let start = offset;
+        offset += line.len() + 1;
         if !in_block {
             if let Some(caps) = RE_FENCE_OPEN.captures(line) {
                 in_block = true;
                 fence = caps[1].to_string();
                 lang = caps.get(2).map_or("", |m| m.as_str()).to_string();
                 buf.clear();
+                content = offset..offset;
+                content_lines = 0;
+                open_line = i + 1;
                 continue;
             }
-            out.push(line.clone());
+            out.push(line.to_string());
+            source_lines.push(i + 1);
         } else if line.trim_end() == fence {
-            let idx = blocks.len();
-            blocks.push(CodeBlock {
-                index: idx,
-                lang: lang.clone(),
-                content: buf.join("\n"),
-            });
+            let idx = push_block(&mut blocks, &lang, &buf, &content);
             out.push(format!("\u{27E6}BLOCK:{}\u{27E7}", idx));
+            source_lines.push(open_line);
             in_block = false;
             fence.clear();
             lang.clear();
             buf.clear();
         } else {
-            buf.push(line.clone());
+            if !spans {
+                buf.push(line);
+            }
+            content.end = start + line.len();
+            content_lines += 1;
         }
     }
 
     // Handle unclosed block
-    if in_block && !buf.is_empty() {
-        let idx = blocks.len();
-        blocks.push(CodeBlock {
+    if in_block && content_lines > 0 {
+        let idx = push_block(&mut blocks, &lang, &buf, &content);
+        out.push(format!("\u{27E6}BLOCK:{}\u{27E7}", idx));
+        source_lines.push(open_line);
+    }
+
+    Stage1Result {
+        lines: out,
+        blocks,
+        source_lines,
+    }
+}
+
+/// Guess whether block content is JSON or YAML from its shape: JSON starts
+/// with `{` or `[` and parses; YAML is two or more lines of `key:` entries
+/// (and `- item`s), with at least one key.
+pub fn infer_data_lang(content: &str) -> Option<&'static str> {
+    let trimmed = content.trim();
+    if trimmed.starts_with(['{', '[']) {
+        return serde_json::from_str::<serde_json::Value>(trimmed).ok().map(|_| "json");
+    }
+    let lines: Vec<&str> = trimmed
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect();
+    let keys = lines.iter().filter(|l| RE_YAML_KEY.is_match(l)).count();
+    let yaml = lines.len() >= 2 && keys > 0 && lines.iter().all(|l| RE_YAML_KEY.is_match(l) || l.starts_with("- "));
+    yaml.then_some("yaml")
+}
+
+/// Turn indented (4+ space) runs that look like JSON or YAML into code blocks
+/// with the inferred language, like fenced blocks in [`stage1`].
+///
+/// A run must follow a blank line (or start the document) and must not
+/// continue a list item or other indented text. Content is dedented by the
+/// run's common indentation. Other indented text is left for stage 2.
+pub fn extract_indented_data(result: &mut Stage1Result) {
+    let is_blank = |l: &String| l.trim().is_empty();
+    let indent = |l: &String| l.len() - l.trim_start().len();
+    let mut lines: Vec<String> = Vec::with_capacity(result.lines.len());
+    let mut source_lines: Vec<usize> = Vec::with_capacity(result.lines.len());
+    let mut i = 0;
+    while i < result.lines.len() {
+        let line = &result.lines[i];
+        let starts_run = !is_blank(line)
+            && indent(line) >= 4
+            && (i == 0 || is_blank(&result.lines[i - 1]))
+            && lines
+                .iter()
+                .rev()
+                .find(|l| !is_blank(l))
+                .is_none_or(|prev| indent(prev) == 0 && !RE_LIST_MARKER.is_match(prev));
+        if starts_run {
+            let mut end = i;
+            let mut k = i;
+            while k < result.lines.len() && (is_blank(&result.lines[k]) || indent(&result.lines[k]) >= 4) {
+                if !is_blank(&result.lines[k]) {
+                    end = k + 1;
+                }
+                k += 1;
+            }
+            let run = &result.lines[i..end];
+            let strip = run.iter().filter(|l| !is_blank(l)).map(indent).min().unwrap_or(0);
+            let content = run
+                .iter()
+                .map(|l| l.get(strip..).unwrap_or("").to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            if let Some(lang) = infer_data_lang(&content) {
+                let idx = result.blocks.len();
+                result.blocks.push(CodeBlock {
+                    index: idx,
+                    lang: lang.to_string(),
+                    content,
+                    span: None,
+                });
+                lines.push(format!("\u{27E6}BLOCK:{}\u{27E7}", idx));
+                source_lines.push(result.source_lines[i]);
+                i = end;
+                continue;
+            }
+        }
+        lines.push(line.clone());
+        source_lines.push(result.source_lines[i]);
+        i += 1;
+    }
+    result.lines = lines;
+    result.source_lines = source_lines;
+}
+
+/// Turn box-drawing ASCII art (`+----+----+` borders around `| a  | b  |` rows)
+/// into code blocks with no language, so stage 2 never reads it as a pipe table
+/// or prose. A run must start and end with a border line and every line in it
+/// must be a border or a `|`/`+`-delimited row. Content is kept verbatim.
+pub fn extract_ascii_art(result: &mut Stage1Result) {
+    let mut lines: Vec<String> = Vec::with_capacity(result.lines.len());
+    let mut source_lines: Vec<usize> = Vec::with_capacity(result.lines.len());
+    let mut i = 0;
+    while i < result.lines.len() {
+        if RE_ART_BORDER.is_match(&result.lines[i]) {
+            let run = result.lines[i..]
+                .iter()
+                .take_while(|l| RE_ART_BORDER.is_match(l) || RE_ART_ROW.is_match(l))
+                .count();
+            // Shrink to the last border so trailing rows stay text
+            let end = (i + 1..i + run).rev().find(|&k| RE_ART_BORDER.is_match(&result.lines[k]));
+            if let Some(end) = end {
+                let idx = result.blocks.len();
+                result.blocks.push(CodeBlock {
+                    index: idx,
+                    lang: String::new(),
+                    content: result.lines[i..=end].join("\n"),
+                    span: None,
+                });
+                lines.push(format!("\u{27E6}BLOCK:{}\u{27E7}", idx));
+                source_lines.push(result.source_lines[i]);
+                i = end + 1;
+                continue;
+            }
+        }
+        lines.push(result.lines[i].clone());
+        source_lines.push(result.source_lines[i]);
+        i += 1;
+    }
+    result.lines = lines;
+    result.source_lines = source_lines;
+}
+
+/// Turn a paragraph that is nothing but one inline code span (`` `make build` ``
+/// between blank lines) into a one-line code block with no language, so it is
+/// emitted verbatim as `::code`. A span sharing its line or paragraph with
+/// other text, or indented under a list item, is left as prose.
+pub fn promote_inline_code_lines(result: &mut Stage1Result) {
+    let blank_at = |i: Option<usize>| i.and_then(|i| result.lines.get(i)).is_none_or(|l| l.trim().is_empty());
+    let mut promoted: Vec<(usize, String)> = Vec::new();
+    for (i, line) in result.lines.iter().enumerate() {
+        if line.starts_with([' ', '\t']) || !blank_at(i.checked_sub(1)) || !blank_at(Some(i + 1)) {
+            continue;
+        }
+        if let Some(caps) = RE_INLINE_CODE_LINE.captures(line.trim_end()) {
+            promoted.push((i, caps[1].to_string()));
+        }
+    }
+    for (i, content) in promoted {
+        let idx = result.blocks.len();
+        result.blocks.push(CodeBlock {
             index: idx,
-            lang: lang.clone(),
-            content: buf.join("\n"),
+            lang: String::new(),
+            content,
+            span: None,
         });
-        out.push(format!("\u{27E6}BLOCK:{}\u{27E7}", idx));
+        result.lines[i] = format!("\u{27E6}BLOCK:{}\u{27E7}", idx);
     }
+}
 
-    Stage1Result { lines: out, blocks }
+/// Remove whitespace outside strings from valid JSON. Returns `None` for
+/// invalid JSON, which is left as written. Numbers and strings are kept
+/// byte-for-byte.
+pub fn minify_json(content: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::Value>(content).ok()?;
+    let mut out = String::with_capacity(content.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in content.chars() {
+        if in_string {
+            out.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+            out.push(c);
+        } else if !c.is_whitespace() {
+            out.push(c);
+        }
+    }
+    Some(out)
 }
 
 #[cfg(test)]
@@ -99,6 +321,90 @@ mod tests {
         assert_eq!(result.blocks[0].content, "```\ninner\n```");
     }
 
+    #[test]
+    fn test_source_lines_skip_block_content() {
+        let lines = s(&["before", "```js", "a", "b", "```", "after"]);
+        let result = stage1(&lines);
+        assert_eq!(result.source_lines, vec![1, 2, 6]);
+    }
+
+    #[test]
+    fn test_spans_match_owned_content() {
+        let docs = [
+            "before\n```js\ncode here\n  indented\n```\nafter",
+            "```py\nx=1\n```\ntext\n```\n```\n",
+            "````\n```\ninner\n```\n````",
+            "```\n\nblank first\n\n```",
+            "```js\ncode\nmore code",
+            "```js\n",
+        ];
+        for doc in docs {
+            let owned = stage1(&doc.split('\n').map(String::from).collect::<Vec<_>>());
+            let spans = stage1_spans(doc);
+            assert_eq!(spans.lines, owned.lines, "{:?}", doc);
+            assert_eq!(spans.source_lines, owned.source_lines);
+            assert_eq!(spans.blocks.len(), owned.blocks.len());
+            for (s, o) in spans.blocks.iter().zip(&owned.blocks) {
+                assert!(s.content.is_empty());
+                assert_eq!(s.text(doc), o.content, "{:?}", doc);
+                assert_eq!(s.lang, o.lang);
+            }
+        }
+    }
+
+    #[test]
+    fn test_infer_json_from_indented_block() {
+        let lines = s(&["Example config:", "", "    {", "      \"port\": 8080,", "      \"debug\": false", "    }", "", "after"]);
+        let mut result = stage1(&lines);
+        extract_indented_data(&mut result);
+        assert_eq!(result.lines, vec!["Example config:", "", "\u{27E6}BLOCK:0\u{27E7}", "", "after"]);
+        assert_eq!(result.source_lines, vec![1, 2, 3, 7, 8]);
+        assert_eq!(result.blocks[0].lang, "json");
+        assert_eq!(result.blocks[0].content, "{\n  \"port\": 8080,\n  \"debug\": false\n}");
+    }
+
+    #[test]
+    fn test_infer_data_lang() {
+        assert_eq!(infer_data_lang("[1, 2]"), Some("json"));
+        assert_eq!(infer_data_lang("server:\n  port: 80\n  hosts:\n    - a"), Some("yaml"));
+        assert_eq!(infer_data_lang("[link] text"), None);
+        assert_eq!(infer_data_lang("just some\nindented prose"), None);
+        assert_eq!(infer_data_lang("name: x"), None);
+    }
+
+    #[test]
+    fn test_indented_data_skips_list_continuations() {
+        let lines = s(&["- item", "", "    {\"a\": 1}", "", "text", "    {\"b\": 2}"]);
+        let mut result = stage1(&lines);
+        extract_indented_data(&mut result);
+        assert!(result.blocks.is_empty());
+        assert_eq!(result.lines, lines);
+    }
+
+    #[test]
+    fn test_ascii_art_table_kept_verbatim() {
+        let art = ["+------+-----+", "| Name | Qty |", "+======+=====+", "| a    |   1 |", "+------+-----+"];
+        let mut lines = s(&["Stock:", ""]);
+        lines.extend(s(&art));
+        lines.extend(s(&["", "+ not art", "| a | b |", "|---|---|"]));
+        let mut result = stage1(&lines);
+        extract_ascii_art(&mut result);
+        assert_eq!(result.blocks.len(), 1);
+        assert_eq!(result.blocks[0].lang, "");
+        assert_eq!(result.blocks[0].content, art.join("\n"));
+        assert_eq!(result.lines, vec!["Stock:", "", "\u{27E6}BLOCK:0\u{27E7}", "", "+ not art", "| a | b |", "|---|---|"]);
+        assert_eq!(result.source_lines, vec![1, 2, 3, 8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn test_minify_json() {
+        assert_eq!(
+            minify_json("{\n  \"a b\": [1, 2.50],\n  \"c\": \"x \\\" y\"\n}").as_deref(),
+            Some("{\"a b\":[1,2.50],\"c\":\"x \\\" y\"}")
+        );
+        assert_eq!(minify_json("{not json"), None);
+    }
+
     #[test]
     fn test_block_with_language() {
         let lines = s(&["```json", r#"{"key": "value"}"#, "```"]);
@@ -1,10 +1,79 @@
-use crate::config::Config;
+use crate::config::{Config, EmphasisMode};
+use crate::reader::{format_kv_pairs, parse_kv_pairs};
 use regex::Regex;
 use std::collections::HashSet;
 use std::sync::LazyLock;
 
 static RE_MULTI_SPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
 static RE_THEMATIC_BREAK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[-*_]{3,}$").unwrap());
+static RE_URL_SPAN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<https?://[^\s<>]+>").unwrap());
+static RE_BYTE_COUNT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(^|[^\d.,])(\d+)\s*(?:(?i:bytes?)|B)\b").unwrap());
+
+const BINARY_SUFFIXES: [(u64, &str); 4] = [(1 << 40, "TiB"), (1 << 30, "GiB"), (1 << 20, "MiB"), (1 << 10, "KiB")];
+const SI_SUFFIXES: [(u64, &str); 4] = [(1_000_000_000_000, "T"), (1_000_000_000, "G"), (1_000_000, "M"), (1_000, "k")];
+
+/// Apply the `units` normalizations to `segment`, logging each unit that matched.
+fn apply_units(segment: &str, unit_regexes: &[(Regex, Regex, String, String)], log: &mut Vec<String>) -> String {
+    let mut segment = segment.to_string();
+    for (re_num, re_standalone, unit, unit_val) in unit_regexes {
+        if re_num.is_match(&segment) || re_standalone.is_match(&segment) {
+            log.push(format!("units: \"{}\" -> \"{}\"", unit, unit_val));
+        }
+        let replacement = format!("${{1}}{}", unit_val);
+        segment = re_num.replace_all(&segment, replacement.as_str()).to_string();
+        segment = re_standalone.replace_all(&segment, unit_val.as_str()).to_string();
+    }
+    segment
+}
+
+/// `n` as an exact multiple of the largest divisor in `suffixes`, e.g. `5000` -> `5k`.
+fn exact_suffix(n: u64, suffixes: &[(u64, &str)]) -> Option<String> {
+    suffixes
+        .iter()
+        .find(|(div, _)| n >= *div && n.is_multiple_of(*div))
+        .map(|(div, suffix)| format!("{}{}", n / div, suffix))
+}
+
+/// Rewrite byte counts (`1073741824 bytes` -> `1GiB`, `5000000 B` -> `5MB`) and,
+/// with an `si` regex, large counts followed by a count word (`5000 requests` ->
+/// `5k requests`). Only exact multiples are rewritten, so no value is rounded.
+fn compact_numbers(segment: &str, bytes: bool, si: Option<&Regex>, log: &mut Vec<String>) -> String {
+    let mut segment = segment.to_string();
+    if bytes {
+        segment = RE_BYTE_COUNT
+            .replace_all(&segment, |caps: &regex::Captures| {
+                let whole = &caps[0][caps[1].len()..];
+                let compact = caps[2].parse::<u64>().ok().and_then(|n| {
+                    exact_suffix(n, &BINARY_SUFFIXES).or_else(|| {
+                        exact_suffix(n, &SI_SUFFIXES).map(|s| format!("{}B", s))
+                    })
+                });
+                match compact {
+                    Some(compact) => {
+                        log.push(format!("byte_suffixes: \"{}\" -> \"{}\"", whole, compact));
+                        format!("{}{}", &caps[1], compact)
+                    }
+                    None => caps[0].to_string(),
+                }
+            })
+            .to_string();
+    }
+    if let Some(re) = si {
+        segment = re
+            .replace_all(&segment, |caps: &regex::Captures| {
+                match caps[2].parse::<u64>().ok().and_then(|n| exact_suffix(n, &SI_SUFFIXES)) {
+                    Some(compact) => {
+                        log.push(format!("si_suffixes: \"{}\" -> \"{}\"", &caps[2], compact));
+                        format!("{}{}{}", &caps[1], compact, &caps[3])
+                    }
+                    None => caps[0].to_string(),
+                }
+            })
+            .to_string();
+    }
+    segment
+}
 
 fn is_text_line(line: &str) -> bool {
     if line.is_empty() {
@@ -22,6 +91,19 @@ fn is_text_line(line: &str) -> bool {
         && !line.starts_with('=')
 }
 
+/// Apply `f` to the parts of `body` outside `<url>` spans, leaving URLs intact.
+fn map_outside_urls(body: &str, mut f: impl FnMut(&str) -> String) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for m in RE_URL_SPAN.find_iter(body) {
+        out.push_str(&f(&body[last..m.start()]));
+        out.push_str(m.as_str());
+        last = m.end();
+    }
+    out.push_str(&f(&body[last..]));
+    out
+}
+
 pub fn compress_c0(lines: &[String]) -> Vec<String> {
     let mut out = Vec::new();
     for line in lines {
@@ -44,134 +126,222 @@ pub fn compress_c1(lines: &[String]) -> Vec<String> {
 }
 
 pub fn compress_c2(lines: &[String], config: &Config) -> Vec<String> {
-    let stopwords: HashSet<String> = config
-        .stopwords
-        .iter()
-        .map(|s| s.to_lowercase())
-        .collect();
-    let protect: HashSet<String> = config
-        .protect_words
-        .iter()
-        .map(|s| s.to_lowercase())
-        .collect();
+    compress_c2_explained(lines, config).0
+}
 
-    // Pre-compile phrase map regexes, sorted by length desc for longest match
-    let mut phrase_entries: Vec<(&String, &String)> = config.phrase_map.iter().collect();
-    phrase_entries.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
-    let phrase_regexes: Vec<(Regex, &str)> = phrase_entries
-        .iter()
-        .map(|(phrase, replacement)| {
-            let re = Regex::new(&format!("(?i){}", regex::escape(phrase))).unwrap();
-            (re, replacement.as_str())
-        })
-        .collect();
-
-    // Pre-compile unit regexes, sorted by length desc for longest match
-    let mut unit_entries: Vec<(&String, &String)> = config.units.iter().collect();
-    unit_entries.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
-    let unit_regexes: Vec<(Regex, Regex, &str)> = unit_entries
-        .iter()
-        .map(|(unit, val)| {
-            let re_num =
-                Regex::new(&format!(r"(?i)(\d+)\s+{}", regex::escape(unit))).unwrap();
-            let re_standalone =
-                Regex::new(&format!("(?i){}", regex::escape(unit))).unwrap();
-            (re_num, re_standalone, val.as_str())
-        })
-        .collect();
-
-    let mut in_block = false;
-
-    lines
-        .iter()
-        .map(|line| {
-            if line == "<<<" {
-                in_block = true;
-                return line.clone();
-            }
-            if line == ">>>" {
-                in_block = false;
-                return line.clone();
-            }
-            if in_block {
-                return line.clone();
-            }
-            if line.starts_with("::") || line.starts_with('@') {
-                return line.clone();
-            }
-
-            let mut text = line.clone();
-
-            // Determine line type
-            let is_text = is_text_line(&text);
-            let is_list = text.starts_with('-');
-            let is_attr = text.starts_with(':');
-
-            let (line_prefix, mut body) = if is_text {
-                ("", text.clone())
-            } else if is_list {
-                ("-", text[1..].to_string())
-            } else if is_attr {
-                (":", text[1..].to_string())
-            } else {
-                return text;
-            };
-
-            // Apply phrase map on text, list, and attribute lines
-            for (re, replacement) in &phrase_regexes {
-                body = re.replace_all(&body, *replacement).to_string();
-            }
-
-            for (re_num, re_standalone, unit_val) in &unit_regexes {
-                let replacement = format!("${{1}}{}", unit_val);
-                body = re_num.replace_all(&body, replacement.as_str()).to_string();
-                body = re_standalone
-                    .replace_all(&body, *unit_val)
-                    .to_string();
-            }
-
-            text = format!("{}{}", line_prefix, body);
-
-            // Stopword removal on text and list lines
-            if is_text || is_list {
-                let prefix2 = if is_list { "-" } else { "" };
-                let body2 = if is_list { &text[1..] } else { &text[..] };
-                let tokens: Vec<&str> = body2.split_whitespace().collect();
-                let filtered: Vec<&str> = tokens
-                    .into_iter()
-                    .filter(|t| {
-                        let low: String = t
-                            .to_lowercase()
-                            .chars()
-                            .filter(|c| c.is_ascii_lowercase())
-                            .collect();
-                        if low.is_empty() {
-                            return true;
-                        }
-                        if protect.contains(&low) {
-                            return true;
+/// Like [`compress_c2`], also returning the edits applied to each line, for `--explain`.
+pub fn compress_c2_explained(lines: &[String], config: &Config) -> (Vec<String>, Vec<Vec<String>>) {
+    C2Compressor::new(config).compress_explained(lines)
+}
+
+/// c2 compression state precompiled from a config (stopword sets, phrase map
+/// and unit regexes), so a batch of documents pays the setup cost once.
+pub struct C2Compressor {
+    stopwords: HashSet<String>,
+    protect: HashSet<String>,
+    /// `!word` emphasis markers are never dropped as stopwords
+    keep_markers: bool,
+    phrase_regexes: Vec<(Regex, String, String)>,
+    unit_regexes: Vec<(Regex, Regex, String, String)>,
+    byte_suffixes: bool,
+    /// Large count followed by one of `si_count_words`, when `si_suffixes` is on
+    si_count: Option<Regex>,
+}
+
+impl C2Compressor {
+    pub fn new(config: &Config) -> Self {
+        let stopwords: HashSet<String> = config
+            .stopwords
+            .iter()
+            .map(|s| s.to_lowercase())
+            .collect();
+        let protect: HashSet<String> = config
+            .protect_words
+            .iter()
+            .map(|s| s.to_lowercase())
+            .collect();
+
+        // Pre-compile phrase map regexes, sorted by length desc for longest match
+        let mut phrase_entries: Vec<(&String, &String)> = config.phrase_map.iter().collect();
+        phrase_entries.sort_by_key(|e| std::cmp::Reverse(e.0.len()));
+        let phrase_regexes = phrase_entries
+            .iter()
+            .map(|(phrase, replacement)| {
+                let re = Regex::new(&format!("(?i){}", regex::escape(phrase))).unwrap();
+                (re, phrase.to_string(), replacement.to_string())
+            })
+            .collect();
+
+        // Pre-compile unit regexes, sorted by length desc for longest match
+        let mut unit_entries: Vec<(&String, &String)> = config.units.iter().collect();
+        unit_entries.sort_by_key(|e| std::cmp::Reverse(e.0.len()));
+        let unit_regexes = unit_entries
+            .iter()
+            .map(|(unit, val)| {
+                let re_num =
+                    Regex::new(&format!(r"(?i)(\d+)\s+{}", regex::escape(unit))).unwrap();
+                let re_standalone =
+                    Regex::new(&format!("(?i){}", regex::escape(unit))).unwrap();
+                (re_num, re_standalone, unit.to_string(), val.to_string())
+            })
+            .collect();
+
+        let si_count = (config.si_suffixes && !config.si_count_words.is_empty()).then(|| {
+            let words: Vec<String> = config.si_count_words.iter().map(|w| regex::escape(w)).collect();
+            Regex::new(&format!(r"(^|[^\d.,])([1-9]\d{{3,}})(\s+(?i:{})\b)", words.join("|"))).unwrap()
+        });
+
+        C2Compressor {
+            stopwords,
+            protect,
+            keep_markers: config.keep_emphasis == EmphasisMode::Marker,
+            phrase_regexes,
+            unit_regexes,
+            byte_suffixes: config.byte_suffixes,
+            si_count,
+        }
+    }
+
+    /// Compress lines at c2, returning the edits applied to each line.
+    pub fn compress_explained(&self, lines: &[String]) -> (Vec<String>, Vec<Vec<String>>) {
+        let C2Compressor {
+            stopwords,
+            protect,
+            keep_markers,
+            phrase_regexes,
+            unit_regexes,
+            byte_suffixes,
+            si_count,
+        } = self;
+        let keep_markers = *keep_markers;
+
+        let mut in_block = false;
+
+        lines
+            .iter()
+            .map(|line| {
+                let mut log: Vec<String> = Vec::new();
+                if line == "<<<" {
+                    in_block = true;
+                    return (line.clone(), log);
+                }
+                if line == ">>>" {
+                    in_block = false;
+                    return (line.clone(), log);
+                }
+                if in_block {
+                    return (line.clone(), log);
+                }
+                if line.starts_with("::") || line.starts_with('@') {
+                    return (line.clone(), log);
+                }
+
+                let mut text = line.clone();
+
+                // Determine line type
+                let is_text = is_text_line(&text);
+                let is_list = text.starts_with('-');
+                let is_attr = text.starts_with(':');
+
+                let (line_prefix, body) = if is_text {
+                    ("", text.clone())
+                } else if is_list {
+                    ("-", text[1..].to_string())
+                } else if is_attr {
+                    (":", text[1..].to_string())
+                } else {
+                    return (text, log);
+                };
+
+                // Apply phrase map on text, list, and attribute lines (never inside URLs)
+                let body = map_outside_urls(&body, |segment| {
+                    let mut segment = segment.to_string();
+                    for (re, phrase, replacement) in phrase_regexes {
+                        if re.is_match(&segment) {
+                            log.push(format!("phrase_map: \"{}\" -> \"{}\"", phrase, replacement));
+                            segment = re.replace_all(&segment, replacement.as_str()).to_string();
                         }
-                        !stopwords.contains(&low)
-                    })
-                    .collect();
-                text = format!("{}{}", prefix2, filtered.join(" "));
-            }
+                    }
+                    segment
+                });
+
+                // Units on attribute lines touch only the values, never the keys
+                let kv_pairs = if is_attr {
+                    parse_kv_pairs(&format!(":{}", body))
+                } else {
+                    None
+                };
+                let body = match kv_pairs {
+                    Some(pairs) => {
+                        let pairs: Vec<(String, String)> = pairs
+                            .into_iter()
+                            .map(|(k, v)| {
+                                let v = map_outside_urls(&v, |segment| apply_units(segment, unit_regexes, &mut log));
+                                (k, v)
+                            })
+                            .collect();
+                        format_kv_pairs(&pairs)[1..].to_string()
+                    }
+                    None => map_outside_urls(&body, |segment| apply_units(segment, unit_regexes, &mut log)),
+                };
+                let body = map_outside_urls(&body, |segment| {
+                    compact_numbers(segment, *byte_suffixes, si_count.as_ref(), &mut log)
+                });
+
+                text = format!("{}{}", line_prefix, body);
+
+                // Stopword removal on text and list lines
+                if is_text || is_list {
+                    let prefix2 = if is_list { "-" } else { "" };
+                    let body2 = if is_list { &text[1..] } else { &text[..] };
+                    let tokens: Vec<&str> = body2.split_whitespace().collect();
+                    let mut removed: Vec<&str> = Vec::new();
+                    let filtered: Vec<&str> = tokens
+                        .into_iter()
+                        .filter(|t| {
+                            if keep_markers && t.starts_with('!') {
+                                return true;
+                            }
+                            let low: String = t
+                                .to_lowercase()
+                                .chars()
+                                .filter(|c| c.is_ascii_lowercase())
+                                .collect();
+                            if low.is_empty() {
+                                return true;
+                            }
+                            if protect.contains(&low) {
+                                return true;
+                            }
+                            if stopwords.contains(&low) {
+                                removed.push(t);
+                                return false;
+                            }
+                            true
+                        })
+                        .collect();
+                    if !removed.is_empty() {
+                        log.push(format!("stopwords: {}", removed.join(", ")));
+                    }
+                    text = format!("{}{}", prefix2, filtered.join(" "));
+                }
 
-            // Trailing period stripping on text and list lines
-            if is_text || is_list {
-                if text.ends_with('.')
+                // Trailing period stripping on text and list lines
+                if (is_text || is_list)
+                    && text.ends_with('.')
                     && !text.ends_with("...")
                     && !text.ends_with("e.g.")
                     && !text.ends_with("i.e.")
                     && !text.ends_with("etc.")
                 {
                     text.pop();
+                    log.push("trailing period stripped".to_string());
                 }
-            }
 
-            text
-        })
-        .collect()
+                (text, log)
+            })
+            .unzip()
+    }
 }
 
 #[cfg(test)]
@@ -187,8 +357,10 @@ mod tests {
 
     #[test]
     fn test_stopword_removal() {
-        let mut config = Config::default();
-        config.stopwords = vec!["the".to_string(), "a".to_string()];
+        let config = Config {
+            stopwords: vec!["the".to_string(), "a".to_string()],
+            ..Default::default()
+        };
         let lines = vec!["-the big a dog".to_string()];
         let result = compress_c2(&lines, &config);
         assert_eq!(result, vec!["-big dog"]);
@@ -196,18 +368,33 @@ mod tests {
 
     #[test]
     fn test_stopword_removal_text_line() {
-        let mut config = Config::default();
-        config.stopwords = vec!["the".to_string(), "a".to_string()];
+        let config = Config {
+            stopwords: vec!["the".to_string(), "a".to_string()],
+            ..Default::default()
+        };
         let lines = vec!["the big a dog".to_string()];
         let result = compress_c2(&lines, &config);
         assert_eq!(result, vec!["big dog"]);
     }
 
+    #[test]
+    fn test_emphasis_markers_survive_stopwords() {
+        let config = Config {
+            stopwords: vec!["the".to_string()],
+            keep_emphasis: EmphasisMode::Marker,
+            ..Default::default()
+        };
+        let lines = vec!["-the !the key".to_string()];
+        assert_eq!(compress_c2(&lines, &config), vec!["-!the key"]);
+    }
+
     #[test]
     fn test_protected_words() {
-        let mut config = Config::default();
-        config.stopwords = vec!["not".to_string()];
-        config.protect_words = vec!["not".to_string()];
+        let config = Config {
+            stopwords: vec!["not".to_string()],
+            protect_words: vec!["not".to_string()],
+            ..Default::default()
+        };
         let lines = vec!["-do not delete".to_string()];
         let result = compress_c2(&lines, &config);
         assert_eq!(result, vec!["-do not delete"]);
@@ -252,6 +439,100 @@ mod tests {
         assert_eq!(result, vec![":timeout=500ms"]);
     }
 
+    #[test]
+    fn test_byte_suffixes() {
+        let config = Config {
+            byte_suffixes: true,
+            ..Default::default()
+        };
+        let lines = vec![
+            ":max_size=1073741824 bytes".to_string(),
+            "-buffer 65536 B, cap 5000000 bytes".to_string(),
+            "1073741824 rows in 1.5 bytes 1000 bytes".to_string(),
+            "1073741824 and 1000 Boxes".to_string(),
+        ];
+        let result = compress_c2(&lines, &config);
+        assert_eq!(result, vec![":max_size=1GiB", "-buffer 64KiB, cap 5MB", "1073741824 rows in 1.5 bytes 1kB", "1073741824 and 1000 Boxes"]);
+    }
+
+    #[test]
+    fn test_si_suffixes() {
+        let config = Config {
+            si_suffixes: true,
+            ..Default::default()
+        };
+        let lines = vec![
+            "-serves 5000 requests, 2000000 users, 1500 hosts".to_string(),
+            "build 20000".to_string(),
+            "listens on port 8000 by default".to_string(),
+            "In 2000 we shipped 3000 Requests".to_string(),
+        ];
+        let result = compress_c2(&lines, &config);
+        assert_eq!(
+            result,
+            vec!["-serves 5k requests, 2M users, 1500 hosts", "build 20000", "listens on port 8000 by default", "In 2000 we shipped 3k Requests"]
+        );
+        let none = Config {
+            si_count_words: Vec::new(),
+            ..config
+        };
+        assert_eq!(compress_c2(&lines[..1], &none), lines[..1]);
+    }
+
+    #[test]
+    fn test_units_touch_kv_values_only() {
+        let mut config = Config::default();
+        config
+            .units
+            .insert("milliseconds".to_string(), "ms".to_string());
+        let lines = vec![
+            ":timeout=up to 500 milliseconds each milliseconds_budget=500 milliseconds".to_string(),
+            ":_col=milliseconds".to_string(),
+            "-waits 20 milliseconds".to_string(),
+        ];
+        let result = compress_c2(&lines, &config);
+        assert_eq!(result, vec![":timeout=up to 500ms each milliseconds_budget=500ms", ":_col=ms", "-waits 20ms"]);
+    }
+
+    #[test]
+    fn test_phrase_map_skips_urls() {
+        let mut config = Config::default();
+        config
+            .phrase_map
+            .insert("specification".to_string(), "spec".to_string());
+        let lines = vec!["specification at <https://example.com/specification>.".to_string()];
+        let result = compress_c2(&lines, &config);
+        assert_eq!(result, vec!["spec at <https://example.com/specification>"]);
+    }
+
+    #[test]
+    fn test_compress_c2_explained() {
+        let mut config = Config {
+            stopwords: vec!["the".to_string()],
+            ..Default::default()
+        };
+        config
+            .phrase_map
+            .insert("in order to".to_string(), "to".to_string());
+        let lines = vec![
+            "@scope".to_string(),
+            "Do the work in order to ship.".to_string(),
+            "-plain".to_string(),
+        ];
+        let (out, edits) = compress_c2_explained(&lines, &config);
+        assert_eq!(out, vec!["@scope", "Do work to ship", "-plain"]);
+        assert!(edits[0].is_empty());
+        assert_eq!(
+            edits[1],
+            vec![
+                "phrase_map: \"in order to\" -> \"to\"",
+                "stopwords: the",
+                "trailing period stripped",
+            ]
+        );
+        assert!(edits[2].is_empty());
+    }
+
     #[test]
     fn test_code_block_protection() {
         let config = Config::default();
@@ -266,8 +547,10 @@ mod tests {
 
     #[test]
     fn test_scope_lines_not_compressed() {
-        let mut config = Config::default();
-        config.stopwords = vec!["the".to_string()];
+        let config = Config {
+            stopwords: vec!["the".to_string()],
+            ..Default::default()
+        };
         let lines = vec!["@the_scope".to_string()];
         let result = compress_c2(&lines, &config);
         assert_eq!(result, vec!["@the_scope"]);
@@ -275,8 +558,10 @@ mod tests {
 
     #[test]
     fn test_block_start_lines_not_compressed() {
-        let mut config = Config::default();
-        config.stopwords = vec!["the".to_string()];
+        let config = Config {
+            stopwords: vec!["the".to_string()],
+            ..Default::default()
+        };
         let lines = vec!["::the_lang".to_string()];
         let result = compress_c2(&lines, &config);
         assert_eq!(result, vec!["::the_lang"]);
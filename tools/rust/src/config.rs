@@ -1,7 +1,11 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::LazyLock;
 
-#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+static RE_DOC_OVERRIDES: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"^\s*<!--\s*llmdc:\s*(\{[\s\S]*?\})\s*-->").unwrap());
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum ScopeMode {
     #[default]
@@ -10,7 +14,49 @@ pub enum ScopeMode {
     Stacked,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TableMode {
+    #[default]
+    Llmd,
+    Csv,
+}
+
+/// What must follow `.`, `!` or `?` and whitespace for `sentence_split` to split.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SentenceSplitNext {
+    /// An uppercase ASCII letter
+    #[default]
+    Upper,
+    /// Any character other than closing punctuation
+    Any,
+}
+
+/// How scope names are cased.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScopeCase {
+    /// Keep source casing at c0/c1 and lowercase at c2
+    #[default]
+    Level,
+    /// Always keep source casing
+    Source,
+    /// Always lowercase
+    Lower,
+}
+
+/// What to do with bold/italic emphasis in text.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmphasisMode {
+    #[default]
+    Strip,
+    /// Bold words become `!word` and survive c2 stopword removal; italic is stripped
+    Marker,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default = "default_compression")]
     pub compression: i32,
@@ -18,21 +64,165 @@ pub struct Config {
     #[serde(default)]
     pub scope_mode: ScopeMode,
 
+    #[serde(default)]
+    pub scope_case: ScopeCase,
+
+    #[serde(default)]
+    pub transliterate_scopes: bool,
+
+    #[serde(default)]
+    pub preserve_anchors: bool,
+
+    #[serde(default)]
+    pub normalize_heading_levels: bool,
+
+    #[serde(default)]
+    pub title_from_filename: bool,
+
+    #[serde(default)]
+    pub distinguish_repeated_headings: bool,
+
+    #[serde(default)]
+    pub emit_scope_level: bool,
+
+    #[serde(default)]
+    pub scope_ids: bool,
+
+    #[serde(default)]
+    pub flatten_single_child_scopes: bool,
+
+    #[serde(default)]
+    pub omit_empty_scopes: bool,
+
+    #[serde(default)]
+    pub lead_scope_name: String,
+
     #[serde(default)]
     pub keep_urls: bool,
 
+    #[serde(default)]
+    pub keep_images: bool,
+
+    #[serde(default)]
+    pub stripped_url_placeholder: bool,
+
     #[serde(default)]
     pub sentence_split: bool,
 
+    #[serde(default)]
+    pub sentence_split_next: SentenceSplitNext,
+
     #[serde(default)]
     pub anchor_every: usize,
 
+    #[serde(default)]
+    pub hoist_summary: bool,
+
+    #[serde(default)]
+    pub emit_checksum: bool,
+
+    #[serde(default)]
+    pub annotate_savings: bool,
+
+    #[serde(default)]
+    pub coalesce_tiny_scopes: usize,
+
+    #[serde(default)]
+    pub max_tokens: usize,
+
+    #[serde(default = "default_truncation_marker")]
+    pub truncation_marker: String,
+
+    #[serde(default)]
+    pub max_input_bytes: usize,
+
+    #[serde(default)]
+    pub min_text_line_tokens: usize,
+
+    #[serde(default)]
+    pub table_mode: TableMode,
+
+    #[serde(default)]
+    pub collapse_single_row_tables: bool,
+
+    #[serde(default)]
+    pub associate_table_captions: bool,
+
+    #[serde(default = "default_multi_value_separator")]
+    pub multi_value_separator: String,
+
+    #[serde(default)]
+    pub table_key_col: String,
+
+    #[serde(default)]
+    pub table_value_col: String,
+
+    #[serde(default)]
+    pub prose_admonitions: bool,
+
+    #[serde(default)]
+    pub rewrite_internal_links: bool,
+
+    #[serde(default = "default_max_nesting_depth")]
+    pub max_nesting_depth: usize,
+
+    #[serde(default = "default_admonition_keywords", serialize_with = "sorted_map")]
+    pub admonition_keywords: HashMap<String, String>,
+
+    #[serde(default)]
+    pub uniform_bullets: bool,
+
+    #[serde(default)]
+    pub inline_short_lists: usize,
+
+    #[serde(default = "default_inline_list_max_tokens")]
+    pub inline_list_max_tokens: usize,
+
+    #[serde(default)]
+    pub allow_numeric_keys: bool,
+
+    #[serde(default)]
+    pub keep_emphasis: EmphasisMode,
+
+    #[serde(default = "default_max_kv_key_len")]
+    pub max_kv_key_len: usize,
+
+    #[serde(default)]
+    pub kv_equals: bool,
+
+    #[serde(default = "default_tab_width")]
+    pub tab_width: usize,
+
     #[serde(default = "default_max_kv_per_line")]
     pub max_kv_per_line: usize,
 
+    #[serde(default)]
+    pub stable_kv_chunks: bool,
+
+    #[serde(default)]
+    pub merge_repeated_kv: bool,
+
+    #[serde(default)]
+    pub compact_pipe_lists: bool,
+
+    #[serde(default = "default_kv_list_separator")]
+    pub kv_list_separator: String,
+
     #[serde(default = "default_bool_compress")]
     pub bool_compress: bool,
 
+    #[serde(default)]
+    pub bool_compress_exclude_cols: Vec<String>,
+
+    #[serde(default, serialize_with = "sorted_map")]
+    pub bool_map: HashMap<String, String>,
+
+    #[serde(default)]
+    pub bool_map_extend: bool,
+
+    #[serde(default = "default_normalize_kv_whitespace")]
+    pub normalize_kv_whitespace: bool,
+
     #[serde(default = "default_prefix_extraction")]
     pub prefix_extraction: bool,
 
@@ -42,17 +232,83 @@ pub struct Config {
     #[serde(default = "default_min_prefix_pct")]
     pub min_prefix_pct: f64,
 
+    #[serde(default = "default_prefix_min_suffix_len")]
+    pub prefix_min_suffix_len: usize,
+
+    #[serde(default = "default_decode_html_entities")]
+    pub decode_html_entities: bool,
+
+    #[serde(default)]
+    pub strip_html: bool,
+
+    #[serde(default)]
+    pub strip_comments: bool,
+
+    #[serde(default = "default_comment_patterns")]
+    pub comment_patterns: Vec<String>,
+
+    #[serde(default)]
+    pub forbidden_patterns: Vec<String>,
+
+    #[serde(default)]
+    pub compress_code_langs: Vec<String>,
+
+    #[serde(default)]
+    pub detect_indented_data: bool,
+
+    #[serde(default)]
+    pub promote_inline_code_lines: bool,
+
+    #[serde(default)]
+    pub ascii_art_as_code: bool,
+
+    #[serde(default)]
+    pub dedup_code: bool,
+
+    #[serde(default, serialize_with = "sorted_map")]
+    pub code_transforms: HashMap<String, String>,
+
+    #[serde(default)]
+    pub drop_scopes: Vec<String>,
+
+    #[serde(default)]
+    pub only_scopes: Vec<String>,
+
+    #[serde(default)]
+    pub sort_scopes: bool,
+
     #[serde(default)]
     pub stopwords: Vec<String>,
 
     #[serde(default)]
     pub protect_words: Vec<String>,
 
-    #[serde(default)]
+    #[serde(default, serialize_with = "sorted_map")]
     pub phrase_map: HashMap<String, String>,
 
-    #[serde(default)]
+    #[serde(default, serialize_with = "sorted_map")]
     pub units: HashMap<String, String>,
+
+    #[serde(default)]
+    pub byte_suffixes: bool,
+
+    #[serde(default)]
+    pub si_suffixes: bool,
+
+    /// Count words a number must be followed by for `si_suffixes` to compact it
+    #[serde(default = "default_si_count_words")]
+    pub si_count_words: Vec<String>,
+
+    #[serde(default = "default_schema_desc_max_len")]
+    pub schema_desc_max_len: usize,
+
+    #[serde(default = "default_schema_desc_ellipsis")]
+    pub schema_desc_ellipsis: String,
+}
+
+/// Serialize a map with sorted keys so printed configs are stable across runs.
+fn sorted_map<S: Serializer>(map: &HashMap<String, String>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
 }
 
 fn default_compression() -> i32 {
@@ -61,61 +317,470 @@ fn default_compression() -> i32 {
 fn default_max_kv_per_line() -> usize {
     4
 }
+fn default_kv_list_separator() -> String {
+    ";".to_string()
+}
+fn default_multi_value_separator() -> String {
+    "¦".to_string()
+}
+fn default_max_nesting_depth() -> usize {
+    32
+}
+fn default_admonition_keywords() -> HashMap<String, String> {
+    [
+        ("note", "note"),
+        ("warning", "warn"),
+        ("caution", "warn"),
+        ("tip", "tip"),
+        ("important", "important"),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect()
+}
+fn default_inline_list_max_tokens() -> usize {
+    3
+}
+fn default_truncation_marker() -> String {
+    "~truncated".to_string()
+}
+fn default_tab_width() -> usize {
+    4
+}
+fn default_max_kv_key_len() -> usize {
+    64
+}
 fn default_bool_compress() -> bool {
     true
 }
+fn default_decode_html_entities() -> bool {
+    true
+}
+fn default_si_count_words() -> Vec<String> {
+    [
+        "requests", "users", "items", "rows", "records", "entries", "events", "messages", "tokens", "calls",
+        "queries", "connections", "files", "lines", "nodes", "hosts", "retries", "samples",
+    ]
+    .iter()
+    .map(|w| w.to_string())
+    .collect()
+}
+fn default_comment_patterns() -> Vec<String> {
+    vec![
+        r"<!--[\s\S]*?-->".to_string(),
+        r"(?m)^ {0,3}\[[^\]]*\]: #(?:\s.*)?$".to_string(),
+    ]
+}
+fn default_normalize_kv_whitespace() -> bool {
+    true
+}
 fn default_prefix_extraction() -> bool {
     true
 }
 fn default_min_prefix_len() -> usize {
     6
 }
+fn default_prefix_min_suffix_len() -> usize {
+    1
+}
 fn default_min_prefix_pct() -> f64 {
     0.6
 }
+fn default_schema_desc_ellipsis() -> String {
+    "...".to_string()
+}
+fn default_schema_desc_max_len() -> usize {
+    200
+}
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             compression: 2,
             scope_mode: ScopeMode::Flat,
+            scope_case: ScopeCase::Level,
+            transliterate_scopes: false,
+            preserve_anchors: false,
+            normalize_heading_levels: false,
+            title_from_filename: false,
+            distinguish_repeated_headings: false,
+            emit_scope_level: false,
+            scope_ids: false,
+            flatten_single_child_scopes: false,
+            omit_empty_scopes: false,
+            lead_scope_name: String::new(),
             keep_urls: false,
+            keep_images: false,
+            stripped_url_placeholder: false,
             sentence_split: false,
+            sentence_split_next: SentenceSplitNext::Upper,
             anchor_every: 0,
+            hoist_summary: false,
+            emit_checksum: false,
+            annotate_savings: false,
+            coalesce_tiny_scopes: 0,
+            max_tokens: 0,
+            truncation_marker: default_truncation_marker(),
+            max_input_bytes: 0,
+            min_text_line_tokens: 0,
+            table_mode: TableMode::Llmd,
+            collapse_single_row_tables: false,
+            associate_table_captions: false,
+            multi_value_separator: default_multi_value_separator(),
+            table_key_col: String::new(),
+            table_value_col: String::new(),
+            prose_admonitions: false,
+            rewrite_internal_links: false,
+            max_nesting_depth: default_max_nesting_depth(),
+            admonition_keywords: default_admonition_keywords(),
+            uniform_bullets: false,
+            inline_short_lists: 0,
+            inline_list_max_tokens: 3,
+            allow_numeric_keys: false,
+            keep_emphasis: EmphasisMode::Strip,
+            max_kv_key_len: 64,
+            kv_equals: false,
+            tab_width: 4,
             max_kv_per_line: 4,
+            stable_kv_chunks: false,
+            merge_repeated_kv: false,
+            compact_pipe_lists: false,
+            kv_list_separator: ";".to_string(),
             bool_compress: true,
+            bool_compress_exclude_cols: Vec::new(),
+            bool_map: HashMap::new(),
+            bool_map_extend: false,
+            normalize_kv_whitespace: true,
             prefix_extraction: true,
             min_prefix_len: 6,
             min_prefix_pct: 0.6,
+            prefix_min_suffix_len: 1,
+            decode_html_entities: true,
+            strip_html: false,
+            strip_comments: false,
+            comment_patterns: default_comment_patterns(),
+            forbidden_patterns: Vec::new(),
+            compress_code_langs: Vec::new(),
+            detect_indented_data: false,
+            promote_inline_code_lines: false,
+            ascii_art_as_code: false,
+            dedup_code: false,
+            code_transforms: HashMap::new(),
+            drop_scopes: Vec::new(),
+            only_scopes: Vec::new(),
+            sort_scopes: false,
             stopwords: Vec::new(),
             protect_words: Vec::new(),
             phrase_map: HashMap::new(),
             units: HashMap::new(),
+            byte_suffixes: false,
+            si_suffixes: false,
+            si_count_words: default_si_count_words(),
+            schema_desc_max_len: 200,
+            schema_desc_ellipsis: default_schema_desc_ellipsis(),
         }
     }
 }
 
+impl Config {
+    /// Check value ranges and pre-compile regex-bearing fields.
+    ///
+    /// Returns every problem found, each naming the field and the accepted values.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems: Vec<String> = Vec::new();
+        if !(0..=2).contains(&self.compression) {
+            problems.push(format!("compression must be 0, 1 or 2 (got {})", self.compression));
+        }
+        if !(0.0..=1.0).contains(&self.min_prefix_pct) {
+            problems.push(format!(
+                "min_prefix_pct must be between 0 and 1 (got {}); use 0.6 for 60%",
+                self.min_prefix_pct
+            ));
+        }
+        if self.max_kv_per_line == 0 {
+            problems.push("max_kv_per_line must be at least 1 (got 0)".to_string());
+        }
+        if self.multi_value_separator.is_empty() {
+            problems.push("multi_value_separator cannot be empty".to_string());
+        }
+        if self.max_kv_key_len == 0 {
+            problems.push("max_kv_key_len must be at least 1 (got 0)".to_string());
+        }
+        for (field, patterns) in [
+            ("comment_patterns", &self.comment_patterns),
+            ("forbidden_patterns", &self.forbidden_patterns),
+        ] {
+            for pattern in patterns {
+                if let Err(e) = regex::Regex::new(pattern) {
+                    problems.push(format!("{}: invalid regex '{}': {}", field, pattern, e));
+                }
+            }
+        }
+        for (field, map) in [
+            ("phrase_map", &self.phrase_map),
+            ("units", &self.units),
+            ("bool_map", &self.bool_map),
+            ("admonition_keywords", &self.admonition_keywords),
+        ] {
+            if map.keys().any(|k| k.trim().is_empty()) {
+                problems.push(format!("{} has an empty key, which would match everywhere", field));
+            }
+        }
+        if !self.truncation_marker.starts_with('~') || self.truncation_marker.contains('\n') {
+            problems.push(format!(
+                "truncation_marker must be a single line starting with '~' (got {:?})",
+                self.truncation_marker
+            ));
+        }
+        for (lang, transform) in &self.code_transforms {
+            if transform != "minify" || lang.to_lowercase() != "json" {
+                problems.push(format!(
+                    "code_transforms: unsupported transform '{}' for '{}' (supported: \"json\": \"minify\")",
+                    transform, lang
+                ));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Layer a document's JSON object of config fields over this config. Only
+    /// [`DOC_OVERRIDE_FIELDS`] may be set; unknown fields and any other field
+    /// (limits, stripping and compliance settings) are errors.
+    pub fn with_overrides(&self, json: &str) -> Result<Config, String> {
+        let patch: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let serde_json::Value::Object(patch) = patch else {
+            return Err("expected a JSON object".to_string());
+        };
+        let mut merged = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        let fields = merged.as_object_mut().expect("config serializes as an object");
+        for (key, value) in patch {
+            if !fields.contains_key(&key) {
+                return Err(format!("unknown config field '{}'", key));
+            }
+            if !DOC_OVERRIDE_FIELDS.contains(&key.as_str()) {
+                return Err(format!("config field '{}' cannot be overridden by a document", key));
+            }
+            fields.insert(key, value);
+        }
+        serde_json::from_value(merged).map_err(|e| e.to_string())
+    }
+}
+
+/// Output-shaping fields a document's override comment may set. Input limits,
+/// token budgets, comment stripping, scope filters and `forbidden_patterns` stay
+/// under the control of whoever runs the compiler.
+pub const DOC_OVERRIDE_FIELDS: &[&str] = &[
+    "compression", "scope_mode", "scope_case", "transliterate_scopes", "preserve_anchors",
+    "normalize_heading_levels", "title_from_filename", "distinguish_repeated_headings", "emit_scope_level",
+    "scope_ids", "flatten_single_child_scopes", "omit_empty_scopes", "lead_scope_name", "keep_urls", "keep_images",
+    "stripped_url_placeholder", "sentence_split", "sentence_split_next", "anchor_every", "hoist_summary",
+    "annotate_savings", "coalesce_tiny_scopes", "min_text_line_tokens", "table_mode",
+    "collapse_single_row_tables", "associate_table_captions", "multi_value_separator", "table_key_col",
+    "table_value_col", "prose_admonitions", "rewrite_internal_links", "admonition_keywords", "uniform_bullets",
+    "inline_short_lists", "inline_list_max_tokens", "allow_numeric_keys", "keep_emphasis", "max_kv_key_len",
+    "kv_equals", "tab_width", "max_kv_per_line", "stable_kv_chunks", "merge_repeated_kv", "compact_pipe_lists",
+    "kv_list_separator", "bool_compress", "bool_compress_exclude_cols", "bool_map", "bool_map_extend",
+    "normalize_kv_whitespace", "prefix_extraction", "min_prefix_len", "min_prefix_pct", "prefix_min_suffix_len",
+    "decode_html_entities", "compress_code_langs", "detect_indented_data", "promote_inline_code_lines",
+    "ascii_art_as_code", "dedup_code", "code_transforms", "sort_scopes", "stopwords", "protect_words",
+    "phrase_map", "units", "byte_suffixes", "si_suffixes", "si_count_words",
+];
+
+/// Split a leading `<!-- llmdc: {...} -->` override comment off a document.
+///
+/// Returns the override JSON and the document with the comment blanked out
+/// (its newlines kept, so line numbers still match the source), or `None`
+/// when the document has no such comment.
+pub fn split_doc_overrides(text: &str) -> Option<(String, String)> {
+    let caps = RE_DOC_OVERRIDES.captures(text)?;
+    let whole = caps.get(0).unwrap();
+    let blank = "\n".repeat(whole.as_str().matches('\n').count());
+    Some((caps[1].to_string(), format!("{}{}", blank, &text[whole.end()..])))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_split_doc_overrides() {
+        let text = "<!-- llmdc: {\"compression\": 1,\n \"keep_urls\": true} -->\n# Title\n";
+        let (json, rest) = split_doc_overrides(text).unwrap();
+        assert_eq!(json, "{\"compression\": 1,\n \"keep_urls\": true}");
+        assert_eq!(rest, "\n\n# Title\n");
+        assert!(split_doc_overrides("# Title\n<!-- llmdc: {} -->\n").is_none());
+        assert!(split_doc_overrides("<!-- a note -->\n# Title\n").is_none());
+    }
+
+    #[test]
+    fn test_with_overrides() {
+        let base = Config {
+            anchor_every: 5,
+            ..Default::default()
+        };
+        let config = base.with_overrides(r#"{"compression": 1, "keep_urls": true}"#).unwrap();
+        assert_eq!(config.compression, 1);
+        assert!(config.keep_urls);
+        assert_eq!(config.anchor_every, 5);
+        assert!(base.with_overrides(r#"{"compresion": 1}"#).unwrap_err().contains("compresion"));
+        assert!(base.with_overrides("[1]").is_err());
+        assert!(base.with_overrides(r#"{"compression": "high"}"#).is_err());
+        for field in ["forbidden_patterns", "comment_patterns", "max_input_bytes", "max_tokens"] {
+            let err = base.with_overrides(&format!(r#"{{"{}": []}}"#, field)).unwrap_err();
+            assert!(err.contains("cannot be overridden"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_doc_override_fields_exist() {
+        let fields = serde_json::to_value(Config::default()).unwrap();
+        for field in DOC_OVERRIDE_FIELDS {
+            assert!(fields.get(field).is_some(), "{}", field);
+        }
+    }
+
+    #[test]
+    fn test_validate_accepts_defaults() {
+        assert_eq!(Config::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_reports_out_of_range_values() {
+        let config = Config {
+            compression: 5,
+            min_prefix_pct: 5.0,
+            max_kv_per_line: 0,
+            comment_patterns: vec!["(unclosed".to_string()],
+            phrase_map: HashMap::from([(" ".to_string(), "x".to_string())]),
+            ..Default::default()
+        };
+        let problems = config.validate().unwrap_err();
+        assert_eq!(problems.len(), 5);
+        assert!(problems[0].starts_with("compression must be 0, 1 or 2 (got 5)"));
+        assert!(problems[1].contains("min_prefix_pct must be between 0 and 1 (got 5)"));
+        assert!(problems[2].contains("max_kv_per_line"));
+        assert!(problems[3].starts_with("comment_patterns: invalid regex '(unclosed'"));
+        assert!(problems[4].starts_with("phrase_map has an empty key"));
+    }
+
+    #[test]
+    fn test_validate_truncation_marker() {
+        let config = Config {
+            truncation_marker: "[cut]".to_string(),
+            ..Default::default()
+        };
+        let problems = config.validate().unwrap_err();
+        assert!(problems[0].starts_with("truncation_marker must be a single line starting with '~'"));
+    }
+
+    #[test]
+    fn test_config_serialization_round_trips() {
+        let config = Config {
+            compression: 1,
+            table_mode: TableMode::Csv,
+            phrase_map: HashMap::from([
+                ("in order to".to_string(), "to".to_string()),
+                ("due to".to_string(), "because".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.find("\"due to\"").unwrap() < json.find("\"in order to\"").unwrap());
+        let back: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+        assert_eq!(back.table_mode, TableMode::Csv);
+    }
+
     #[test]
     fn test_default_config() {
         let config = Config::default();
         assert_eq!(config.compression, 2);
         assert_eq!(config.scope_mode, ScopeMode::Flat);
+        assert_eq!(config.scope_case, ScopeCase::Level);
+        assert!(!config.transliterate_scopes);
+        assert!(!config.preserve_anchors);
+        assert!(!config.normalize_heading_levels);
+        assert!(!config.title_from_filename);
+        assert!(!config.distinguish_repeated_headings);
+        assert!(!config.emit_scope_level);
+        assert!(!config.scope_ids);
+        assert!(!config.flatten_single_child_scopes);
+        assert!(!config.omit_empty_scopes);
+        assert!(config.lead_scope_name.is_empty());
         assert!(!config.keep_urls);
+        assert!(!config.keep_images);
+        assert!(!config.stripped_url_placeholder);
         assert!(!config.sentence_split);
+        assert_eq!(config.sentence_split_next, SentenceSplitNext::Upper);
         assert_eq!(config.anchor_every, 0);
+        assert!(!config.hoist_summary);
+        assert!(!config.emit_checksum);
+        assert!(!config.annotate_savings);
+        assert_eq!(config.coalesce_tiny_scopes, 0);
+        assert_eq!(config.max_tokens, 0);
+        assert_eq!(config.truncation_marker, "~truncated");
+        assert_eq!(config.max_input_bytes, 0);
+        assert_eq!(config.min_text_line_tokens, 0);
+        assert_eq!(config.table_mode, TableMode::Llmd);
+        assert_eq!(config.keep_emphasis, EmphasisMode::Strip);
+        assert!(!config.collapse_single_row_tables);
+        assert!(!config.associate_table_captions);
+        assert_eq!(config.multi_value_separator, "¦");
+        assert!(config.table_key_col.is_empty());
+        assert!(config.table_value_col.is_empty());
+        assert!(!config.prose_admonitions);
+        assert!(!config.rewrite_internal_links);
+        assert_eq!(config.max_nesting_depth, 32);
+        assert_eq!(config.admonition_keywords.len(), 5);
+        assert_eq!(config.admonition_keywords["warning"], "warn");
+        assert!(!config.uniform_bullets);
+        assert_eq!(config.inline_short_lists, 0);
+        assert_eq!(config.inline_list_max_tokens, 3);
+        assert!(!config.allow_numeric_keys);
+        assert_eq!(config.max_kv_key_len, 64);
+        assert!(!config.kv_equals);
+        assert_eq!(config.tab_width, 4);
         assert_eq!(config.max_kv_per_line, 4);
+        assert!(!config.stable_kv_chunks);
+        assert!(!config.merge_repeated_kv);
+        assert!(!config.compact_pipe_lists);
+        assert_eq!(config.kv_list_separator, ";");
         assert!(config.bool_compress);
+        assert!(config.bool_compress_exclude_cols.is_empty());
+        assert!(config.bool_map.is_empty());
+        assert!(!config.bool_map_extend);
+        assert!(config.normalize_kv_whitespace);
         assert!(config.prefix_extraction);
         assert_eq!(config.min_prefix_len, 6);
         assert!((config.min_prefix_pct - 0.6).abs() < f64::EPSILON);
+        assert_eq!(config.prefix_min_suffix_len, 1);
+        assert!(config.decode_html_entities);
+        assert!(!config.strip_html);
+        assert!(!config.strip_comments);
+        assert_eq!(config.comment_patterns.len(), 2);
+        assert!(config.forbidden_patterns.is_empty());
+        assert!(config.compress_code_langs.is_empty());
+        assert!(!config.detect_indented_data);
+        assert!(!config.promote_inline_code_lines);
+        assert!(!config.ascii_art_as_code);
+        assert!(!config.dedup_code);
+        assert!(config.code_transforms.is_empty());
+        assert!(config.drop_scopes.is_empty());
+        assert!(config.only_scopes.is_empty());
+        assert!(!config.sort_scopes);
         assert!(config.stopwords.is_empty());
         assert!(config.protect_words.is_empty());
         assert!(config.phrase_map.is_empty());
         assert!(config.units.is_empty());
+        assert!(!config.byte_suffixes);
+        assert!(!config.si_suffixes);
+        assert!(config.si_count_words.contains(&"requests".to_string()));
+        assert_eq!(config.schema_desc_max_len, 200);
+        assert_eq!(config.schema_desc_ellipsis, "...");
     }
 
     #[test]
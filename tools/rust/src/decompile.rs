@@ -0,0 +1,387 @@
+use crate::config::{Config, ScopeMode};
+use crate::reader::{format_kv_pairs, parse_kv_pairs, read_llmd, LlmdScope};
+
+/// Turn LLMD back into Markdown for reading in a Markdown viewer.
+///
+/// Scopes become headings, `:k=v` pairs become `k: v` lines (with any
+/// `:_pfx=` prefix restored), `:_col=`/`:_cols=` runs become pipe tables,
+/// `-` items become (nested) list items and `::lang` blocks become fences.
+/// Heading levels come from a `#level` suffix when present, else from the
+/// `_`-joined parent names under `concat`/`stacked` scope modes; flat names
+/// give the first scope `#` and the rest `##`. With `coalesce_tiny_scopes`, a
+/// merged scope is split back into the scopes named by its sub-keys. Bookkeeping
+/// lines (`~sum`, `~tokens`, the truncation marker) are dropped.
+///
+/// Lossy above c0 (stopwords and punctuation are gone), but compiling the
+/// result again gives the same scopes, KV pairs and code blocks.
+pub fn decompile(llmd: &str, config: &Config) -> String {
+    let mut scopes = read_llmd(llmd);
+    if config.coalesce_tiny_scopes > 0 {
+        scopes = scopes
+            .into_iter()
+            .flat_map(|s| split_coalesced(&s).unwrap_or_else(|| vec![s]))
+            .collect();
+    }
+    let mut blocks: Vec<String> = Vec::new();
+    // Contents of the `<<<` blocks seen so far, for `::lang ref=N`
+    let mut code: Vec<String> = Vec::new();
+    let mut parents: Vec<String> = Vec::new();
+    let mut first_heading = true;
+    for scope in &scopes {
+        if !scope.name.is_empty() {
+            let (level, title) = heading(&scope.name, &mut parents, first_heading, config);
+            first_heading = false;
+            blocks.push(format!("{} {}", "#".repeat(level), title));
+        }
+        body_blocks(&scope.body, config, &mut code, &mut blocks);
+    }
+    if blocks.is_empty() {
+        return String::new();
+    }
+    blocks.join("\n\n") + "\n"
+}
+
+/// The scopes a `coalesce_tiny_scopes` merge folded into `scope`, recovered from
+/// its `:name.k=v` and `-name: text` sub-keys, or `None` when `scope` is not such
+/// a merge: every line must carry a sub-key, and every sub-key name must extend
+/// the merged scope's name (or the merged scope is `scopes`).
+fn split_coalesced(scope: &LlmdScope) -> Option<Vec<LlmdScope>> {
+    let is_name = |n: &str| !n.is_empty() && n.chars().all(|c| c.is_alphanumeric() || "_-#".contains(c));
+    let mut out: Vec<LlmdScope> = Vec::new();
+    for entry in &scope.body {
+        let (name, line) = match parse_kv_pairs(entry) {
+            Some(pairs) => {
+                let name = pairs[0].0.split_once('.')?.0.to_string();
+                let mut rest = Vec::new();
+                for (key, value) in pairs {
+                    let (n, k) = key.split_once('.')?;
+                    if n != name {
+                        return None;
+                    }
+                    rest.push((k.to_string(), value));
+                }
+                (name, format_kv_pairs(&rest))
+            }
+            None => {
+                let (name, text) = entry.strip_prefix('-')?.split_once(": ")?;
+                (name.to_string(), text.to_string())
+            }
+        };
+        let base = name.split('#').next().unwrap_or("");
+        let extends = scope.name == "scopes" || base == scope.name || base.starts_with(&format!("{}_", scope.name));
+        if !is_name(&name) || !extends {
+            return None;
+        }
+        match out.last_mut() {
+            Some(last) if last.name == name => last.body.push(line),
+            _ => out.push(LlmdScope { name, body: vec![line] }),
+        }
+    }
+    (out.len() >= 2).then_some(out)
+}
+
+/// Heading level and text for a scope name. `parents` holds the enclosing
+/// scope names under `concat`/`stacked` modes.
+fn heading(name: &str, parents: &mut Vec<String>, first: bool, config: &Config) -> (usize, String) {
+    let mut parts = name.split('#');
+    let base = parts.next().unwrap_or("");
+    let suffix_level = parts
+        .filter_map(|p| p.parse::<usize>().ok())
+        .find(|l| (1..=6).contains(l));
+    let mut title = base;
+    let mut level = if first { 1 } else { 2 };
+    if config.scope_mode != ScopeMode::Flat {
+        while parents.last().is_some_and(|p| !base.starts_with(&format!("{}_", p))) {
+            parents.pop();
+        }
+        if let Some(parent) = parents.last() {
+            title = &base[parent.len() + 1..];
+        }
+        level = parents.len() + 1;
+        parents.push(base.to_string());
+    }
+    // A doubled `_` is punctuation the name dropped (`A — B` -> `A__B`)
+    let title = title.replace("__", " — ").replace('_', " ");
+    (suffix_level.unwrap_or(level).min(6), title)
+}
+
+/// Append the Markdown blocks for one scope body.
+fn body_blocks(body: &[String], config: &Config, code: &mut Vec<String>, blocks: &mut Vec<String>) {
+    let mut prefix = String::new();
+    let mut admonition: Option<String> = None;
+    let mut i = 0;
+    while i < body.len() {
+        let entry = &body[i];
+        i += 1;
+        if !entry.starts_with(':') || entry.starts_with("::") {
+            prefix.clear();
+        }
+        if entry.contains('\n') {
+            let content = block_content(entry);
+            code.push(content.clone());
+            blocks.push(fence(block_lang(entry), &content));
+        } else if let Some(rest) = entry.strip_prefix("::") {
+            if let Some((lang, n)) = rest.rsplit_once(" ref=") {
+                let content = n.parse::<usize>().ok().and_then(|n| code.get(n)).cloned().unwrap_or_default();
+                blocks.push(fence(lang, &content));
+            } else if let Some(lang) = rest.strip_suffix(" (empty)") {
+                blocks.push(fence(lang, ""));
+            }
+        } else if let Some(marker) = entry.strip_prefix('~') {
+            admonition = admonition_prefix(marker, config);
+        } else if let Some(p) = entry.strip_prefix(":_pfx=") {
+            prefix = p.to_string();
+        } else if let Some(header) = entry.strip_prefix(":_col=") {
+            let mut rows: Vec<Vec<String>> = vec![vec!["Key".to_string(), header.to_string()]];
+            while let Some(pairs) = body.get(i).and_then(|e| kv_or_prefix(e, &mut prefix)) {
+                rows.extend(pairs.into_iter().map(|(k, v)| vec![k, v]));
+                i += 1;
+            }
+            blocks.push(table(&rows));
+        } else if let Some(header) = entry.strip_prefix(":_cols=") {
+            // Keyed tables join cells with the configured separator, raw ones with `¦`
+            let sep = match config.multi_value_separator.as_str() {
+                sep if split_cells(header, sep).len() > 1 => sep,
+                _ => "¦",
+            };
+            let headers = split_cells(header, sep);
+            let mut rows: Vec<Vec<String>> = vec![headers.clone()];
+            let mut raw = false;
+            while let Some(next) = body.get(i) {
+                if let Some(pairs) = kv_or_prefix(next, &mut prefix) {
+                    for (k, v) in pairs {
+                        let mut row = vec![k];
+                        row.extend(split_cells(&v, sep));
+                        rows.push(row);
+                    }
+                } else if is_raw_row(next, sep, headers.len()) {
+                    rows.push(split_cells(next, sep));
+                    raw = true;
+                } else {
+                    break;
+                }
+                i += 1;
+            }
+            // Code-format the first column of a raw table that would otherwise
+            // compile back as keys, which would normalize its cells
+            if raw && crate::emit::classify_table(&rows, config.allow_numeric_keys) != "raw" {
+                for cell in rows[1..].iter_mut().filter_map(|r| r.first_mut()).filter(|c| !c.is_empty()) {
+                    *cell = format!("`{}`", cell);
+                }
+            }
+            blocks.push(table(&rows));
+        } else if let Some(caption) = entry.strip_prefix(":_caption=") {
+            blocks.push(caption.to_string());
+        } else if entry.starts_with(":_") {
+            // Other reserved attribute lines carry no content
+        } else if let Some(pairs) = parse_kv_pairs(entry) {
+            let lines: Vec<String> = pairs
+                .into_iter()
+                .map(|(k, v)| format!("{}{}: {}", prefix, k, v))
+                .collect();
+            blocks.push(lines.join("\n"));
+        } else if entry.starts_with('-') {
+            let mut items = vec![list_item(entry)];
+            while let Some(next) = body.get(i).filter(|e| e.starts_with('-')) {
+                items.push(list_item(next));
+                i += 1;
+            }
+            blocks.push(items.join("\n"));
+        } else {
+            let text = match admonition.take() {
+                Some(kind) => format!("{}: {}", kind, entry),
+                None => entry.clone(),
+            };
+            blocks.push(text);
+        }
+    }
+}
+
+/// The KV pairs of `entry` with `prefix` restored, updating `prefix` when the
+/// entry is a `:_pfx=` line. `None` for anything else.
+fn kv_or_prefix(entry: &str, prefix: &mut String) -> Option<Vec<(String, String)>> {
+    if let Some(p) = entry.strip_prefix(":_pfx=") {
+        *prefix = p.to_string();
+        return Some(Vec::new());
+    }
+    let pairs = parse_kv_pairs(entry)?;
+    Some(pairs.into_iter().map(|(k, v)| (format!("{}{}", prefix, k), v)).collect())
+}
+
+/// Whether `line` is a `raw` table row of `cols` cells separated by `sep`.
+fn is_raw_row(line: &str, sep: &str, cols: usize) -> bool {
+    !line.starts_with(['@', ':', '-', '~']) && split_cells(line, sep).len() == cols && cols > 1
+}
+
+/// Split a joined table value on `sep`, honoring `\` escapes of the separator.
+fn split_cells(value: &str, sep: &str) -> Vec<String> {
+    let escaped = format!("\\{}", sep);
+    let mut cells: Vec<String> = vec![String::new()];
+    let mut rest = value;
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix(escaped.as_str()) {
+            cells.last_mut().unwrap().push_str(sep);
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix(sep) {
+            cells.push(String::new());
+            rest = r;
+        } else {
+            let c = rest.chars().next().unwrap();
+            cells.last_mut().unwrap().push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    cells
+}
+
+fn table(rows: &[Vec<String>]) -> String {
+    let row = |cells: &[String]| {
+        let cells: Vec<String> = cells.iter().map(|c| c.replace('|', "\\|")).collect();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines = vec![row(&rows[0])];
+    lines.push(format!("|{}", "---|".repeat(rows[0].len())));
+    lines.extend(rows[1..].iter().map(|r| row(r)));
+    lines.join("\n")
+}
+
+/// A `-` line as a Markdown list item: `-text`, `-. text` (nested) or a
+/// `-+ text` continuation paragraph.
+fn list_item(line: &str) -> String {
+    let rest = &line[1..];
+    if let Some(text) = rest.strip_prefix('\\') {
+        return format!("- {}", text);
+    }
+    let depth = rest.chars().take_while(|&c| c == '.').count();
+    let rest = &rest[depth..];
+    match rest.strip_prefix('+') {
+        Some(text) => format!("\n{}{}", "  ".repeat(depth + 1), text.trim_start()),
+        None => format!("{}- {}", "  ".repeat(depth), rest.trim_start()),
+    }
+}
+
+/// `Note`-style prefix for a `~kind` admonition marker: the `admonition_keywords`
+/// entry for it, preferring one that spells the marker out (`warn` -> `Warning`).
+fn admonition_prefix(marker: &str, config: &Config) -> Option<String> {
+    let mut keywords: Vec<&String> = config
+        .admonition_keywords
+        .iter()
+        .filter(|(_, v)| v.as_str() == marker)
+        .map(|(k, _)| k)
+        .collect();
+    keywords.sort_by_key(|k| (!k.starts_with(marker), k.to_string()));
+    let keyword = keywords.first()?;
+    let mut chars = keyword.chars();
+    let first = chars.next()?;
+    Some(first.to_uppercase().chain(chars).collect())
+}
+
+fn block_lang(entry: &str) -> &str {
+    match entry.lines().next().and_then(|l| l.strip_prefix("::")) {
+        Some("code") | None => "",
+        Some(lang) => lang,
+    }
+}
+
+/// The lines between `<<<` and `>>>` of a block entry.
+fn block_content(entry: &str) -> String {
+    let lines: Vec<&str> = entry
+        .lines()
+        .skip_while(|l| *l != "<<<")
+        .skip(1)
+        .take_while(|l| *l != ">>>")
+        .collect();
+    lines.join("\n")
+}
+
+fn fence(lang: &str, content: &str) -> String {
+    let lang = if lang == "code" { "" } else { lang };
+    if content.is_empty() {
+        return format!("```{}\n```", lang);
+    }
+    format!("```{}\n{}\n```", lang, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompile_markers() {
+        let llmd = "@Guide\nIntro line.\n~warn\nBack up first.\n:_pfx=db_\n:host=x port=5432\n-one\n-. nested\n-.+ more\n@Limits\n:_col=limit\n:rps=10\n@Matrix\n:_cols=name¦type¦notes\n:id=int¦a\\¦b\n::sh\n<<<\nrun it\n>>>\n::sh ref=0\n::py (empty)\n~sum abc\n";
+        let md = decompile(llmd, &Config::default());
+        assert_eq!(
+            md,
+            "# Guide\n\nIntro line.\n\nWarning: Back up first.\n\ndb_host: x\ndb_port: 5432\n\n- one\n  - nested\n\n    more\n\n## Limits\n\n| Key | limit |\n|---|---|\n| rps | 10 |\n\n## Matrix\n\n| name | type | notes |\n|---|---|---|\n| id | int | a¦b |\n\n```sh\nrun it\n```\n\n```sh\nrun it\n```\n\n```py\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_decompile_table_separator() {
+        let config = Config {
+            multi_value_separator: "|".to_string(),
+            ..Default::default()
+        };
+        let md = decompile("@t\n:_cols=name|type\n:id=int\n@r\n:_cols=a¦b¦c\n1¦2¦3\nx|y\n", &config);
+        assert_eq!(
+            md,
+            "# t\n\n| name | type |\n|---|---|\n| id | int |\n\n## r\n\n| a | b | c |\n|---|---|---|\n| 1 | 2 | 3 |\n\nx|y\n"
+        );
+    }
+
+    #[test]
+    fn test_decompile_dedup_refs_with_csv_tables() {
+        let config = Config {
+            dedup_code: true,
+            table_mode: crate::config::TableMode::Csv,
+            ..Default::default()
+        };
+        let md = "# Setup\n\n```py\nimport os\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n```sh\nmake\n```\n\n```sh\nmake\n```\n";
+        let compiled = crate::compile(md, &config);
+        assert!(compiled.ends_with("::sh ref=2\n"), "{}", compiled);
+        let decompiled = decompile(&compiled, &config);
+        assert!(decompiled.ends_with("```csv\na,b\n1,2\n```\n\n```sh\nmake\n```\n\n```sh\nmake\n```\n"), "{}", decompiled);
+        assert!(crate::compile(&decompiled, &config).ends_with("::sh\n<<<\nmake\n>>>\n::sh ref=2\n"));
+    }
+
+    #[test]
+    fn test_decompile_keeps_raw_tables_raw() {
+        let llmd = "@t\n:_cols=attr¦effect\ndata-icon=\"Name\"¦Adds an icon\ndisabled¦Native state\n";
+        let md = decompile(llmd, &Config::default());
+        assert_eq!(
+            md,
+            "# t\n\n| attr | effect |\n|---|---|\n| `data-icon=\"Name\"` | Adds an icon |\n| `disabled` | Native state |\n"
+        );
+        assert_eq!(crate::compile(&md, &Config::default()), llmd);
+    }
+
+    #[test]
+    fn test_decompile_splits_coalesced_scopes() {
+        let config = Config {
+            coalesce_tiny_scopes: 1,
+            ..Default::default()
+        };
+        let llmd = "@intro\nOverview\nmore\n@get\n:get_users.method=GET get_users.path=/users\n-get_orders: Lists orders\n-get_items#2: paged\n@notes\n-Tip: keep it\n-Also: this\n";
+        let md = decompile(llmd, &config);
+        assert_eq!(
+            md,
+            "# intro\n\nOverview\n\nmore\n\n## get users\n\nmethod: GET\npath: /users\n\n## get orders\n\nLists orders\n\n## get items\n\npaged\n\n## notes\n\n- Tip: keep it\n- Also: this\n"
+        );
+        let md = "# API\n\nOverview of the API.\n\nMore detail.\n\n## Get users\n\nmethod: GET\n\n## Get orders\n\n- paged\n";
+        let compiled = crate::compile(md, &config);
+        assert!(compiled.contains("@get\n"));
+        assert_eq!(crate::compile(&decompile(&compiled, &config), &config), compiled);
+    }
+
+    #[test]
+    fn test_decompile_concat_heading_levels() {
+        let config = Config {
+            scope_mode: ScopeMode::Concat,
+            ..Default::default()
+        };
+        let md = decompile("@setup\na\n@setup_linux\nb\n@setup_linux_arm\nc\n@usage\nd\n", &config);
+        assert_eq!(md, "# setup\n\na\n\n## linux\n\nb\n\n### arm\n\nc\n\n# usage\n\nd\n");
+        let md = decompile("@intro#1\na\n@details#3\nb\n", &Config::default());
+        assert_eq!(md, "# intro\n\na\n\n### details\n\nb\n");
+    }
+}
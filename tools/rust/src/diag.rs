@@ -0,0 +1,186 @@
+use serde::Serialize;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+static DEBUG: AtomicBool = AtomicBool::new(false);
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// How much non-error output goes to stderr. Errors are always printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// No summaries; warnings only under strict mode
+    Quiet,
+    Normal,
+    /// Also report per-file progress
+    Verbose,
+}
+
+pub fn set_verbosity(v: Verbosity) {
+    VERBOSITY.store(v as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// In strict mode warnings are printed even when quiet.
+pub fn set_strict(enabled: bool) {
+    STRICT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn warnings_enabled() -> bool {
+    shows_warnings(verbosity(), STRICT.load(Ordering::Relaxed))
+}
+
+fn shows_warnings(verbosity: Verbosity, strict: bool) -> bool {
+    verbosity > Verbosity::Quiet || strict
+}
+
+/// Print a warning to stderr unless quiet output suppresses it.
+pub fn warn(msg: &str) {
+    if warnings_enabled() {
+        eprintln!("{}", msg);
+    }
+}
+
+/// Print an informational message (e.g. a compile summary) unless quiet.
+pub fn info(msg: &str) {
+    if verbosity() >= Verbosity::Normal {
+        eprintln!("{}", msg);
+    }
+}
+
+/// Print a message only with verbose output.
+pub fn verbose(msg: &str) {
+    if verbosity() >= Verbosity::Verbose {
+        eprintln!("{}", msg);
+    }
+}
+
+/// What a [`Diagnostic`] is about. Names are stable; `--fail-on` and
+/// `--report` use them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticKind {
+    /// Content line before the first `@scope`
+    ScopeOrder,
+    /// Output matches a `forbidden_patterns` entry
+    ForbiddenPattern,
+    /// `<<<` without a matching `>>>`, or the reverse
+    UnbalancedBlock,
+    /// Input already looked like LLMD and was passed through
+    LlmdInput,
+    /// `${VAR}` with no value under `--env-subst`
+    UndefinedVar,
+    /// An `only_scopes` name matched no scope
+    UnknownScope,
+    /// List items nested deeper than `max_nesting_depth` were flattened
+    DeepNesting,
+}
+
+impl DiagnosticKind {
+    pub const ALL: [DiagnosticKind; 7] = [
+        DiagnosticKind::ScopeOrder,
+        DiagnosticKind::ForbiddenPattern,
+        DiagnosticKind::UnbalancedBlock,
+        DiagnosticKind::LlmdInput,
+        DiagnosticKind::UndefinedVar,
+        DiagnosticKind::UnknownScope,
+        DiagnosticKind::DeepNesting,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DiagnosticKind::ScopeOrder => "scope-order",
+            DiagnosticKind::ForbiddenPattern => "forbidden-pattern",
+            DiagnosticKind::UnbalancedBlock => "unbalanced-block",
+            DiagnosticKind::LlmdInput => "llmd-input",
+            DiagnosticKind::UndefinedVar => "undefined-var",
+            DiagnosticKind::UnknownScope => "unknown-scope",
+            DiagnosticKind::DeepNesting => "deep-nesting",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DiagnosticKind> {
+        DiagnosticKind::ALL.into_iter().find(|k| k.name() == name)
+    }
+}
+
+/// A warning found while compiling, tagged with its kind.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(kind: DiagnosticKind, message: impl Into<String>) -> Self {
+        Diagnostic {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Whether any diagnostic is of a kind listed in `fail_on`.
+pub fn should_fail(diagnostics: &[Diagnostic], fail_on: &[DiagnosticKind]) -> bool {
+    diagnostics.iter().any(|d| fail_on.contains(&d.kind))
+}
+
+/// Enable or disable debug diagnostics on stderr.
+pub fn set_debug(enabled: bool) {
+    DEBUG.store(enabled, Ordering::Relaxed);
+}
+
+pub fn debug_enabled() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+/// Report a debug diagnostic for a source line, if debug output is enabled.
+pub fn debug(line: usize, msg: &str) {
+    if debug_enabled() {
+        eprintln!("debug: line {}: {}", line, msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostic_kind_names_round_trip() {
+        for kind in DiagnosticKind::ALL {
+            assert_eq!(DiagnosticKind::from_name(kind.name()), Some(kind));
+            assert_eq!(serde_json::to_value(kind).unwrap(), kind.name());
+        }
+        assert_eq!(DiagnosticKind::from_name("nope"), None);
+    }
+
+    #[test]
+    fn test_should_fail_only_on_listed_kinds() {
+        let diagnostics = vec![Diagnostic::new(DiagnosticKind::ScopeOrder, "validation warning: line 1")];
+        assert!(!should_fail(&diagnostics, &[DiagnosticKind::ForbiddenPattern]));
+        assert!(!should_fail(&diagnostics, &[]));
+        assert!(should_fail(&diagnostics, &[DiagnosticKind::UnbalancedBlock, DiagnosticKind::ScopeOrder]));
+    }
+
+    // Tests the rule, not the process-wide settings other tests read in parallel
+    #[test]
+    fn test_quiet_suppresses_warnings_unless_strict() {
+        assert!(!shows_warnings(Verbosity::Quiet, false));
+        assert!(shows_warnings(Verbosity::Quiet, true));
+        assert!(shows_warnings(Verbosity::Normal, false));
+        assert!(shows_warnings(Verbosity::Verbose, false));
+    }
+}
@@ -1,13 +1,22 @@
-use crate::config::{Config, ScopeMode};
-use crate::inline::process_inline;
-use crate::ir::{CodeBlock, IrNode};
+use crate::config::{Config, ScopeCase, ScopeMode, SentenceSplitNext, TableMode};
+use crate::inline::{decode_html_entities_literal, internal_link_refs, process_inline_with, strip_html};
+use crate::ir::{CodeBlock, IrNode, ListStyle};
+use crate::scopemap::ScopeOrigin;
 use crate::scope::{norm_key, norm_scope_name};
+use deunicode::deunicode;
 use fancy_regex::Regex as FancyRegex;
 use std::collections::{HashMap, HashSet};
 use std::sync::LazyLock;
 
 static RE_SENTENCE_SPLIT: LazyLock<FancyRegex> =
     LazyLock::new(|| FancyRegex::new(r"(?<=[.!?])\s+(?=[A-Z])").unwrap());
+static RE_SENTENCE_SPLIT_ANY: LazyLock<FancyRegex> =
+    LazyLock::new(|| FancyRegex::new(r#"(?<=[.!?])\s+(?=[^\s.,;:!?)\]}"'])"#).unwrap());
+static RE_WHITESPACE: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"\s+").unwrap());
+
+/// Abbreviations whose period doesn't end a sentence when splitting before any word.
+const ABBREVIATIONS: &[&str] = &["e.g.", "i.e.", "vs.", "cf."];
 
 const GENERIC_HEADERS: &[&str] = &[
     "value",
@@ -27,7 +36,9 @@ fn is_informative_header(header: &str) -> bool {
     !GENERIC_HEADERS.contains(&low.as_str())
 }
 
-fn classify_table(rows: &[Vec<String>]) -> &'static str {
+/// Classify a table as `property`, `keyed_multi` or `raw`. With
+/// `allow_numeric_keys`, a first column of plain numbers (IDs) also counts as keys.
+pub(crate) fn classify_table(rows: &[Vec<String>], allow_numeric_keys: bool) -> &'static str {
     if rows.len() < 2 {
         return "raw";
     }
@@ -49,7 +60,8 @@ fn classify_table(rows: &[Vec<String>]) -> &'static str {
             return "raw";
         }
         first_col_vals.insert(val.clone());
-        if !re_ident.is_match(&val) || val.split_whitespace().count() > 4 {
+        let numeric = allow_numeric_keys && !val.is_empty() && val.bytes().all(|b| b.is_ascii_digit());
+        if !(re_ident.is_match(&val) || numeric) || val.split_whitespace().count() > 4 {
             return "raw";
         }
     }
@@ -59,39 +71,75 @@ fn classify_table(rows: &[Vec<String>]) -> &'static str {
     "keyed_multi"
 }
 
-fn bool_map() -> HashMap<&'static str, &'static str> {
+/// The key and value columns named by `table_key_col`/`table_value_col`, matched
+/// against normalized header names. The value column defaults to the one after
+/// the key. `None` when the key column is unset or either column is missing.
+fn property_columns(header: &[String], config: &Config) -> Option<(usize, usize)> {
+    if config.table_key_col.is_empty() {
+        return None;
+    }
+    let find = |name: &str| header.iter().position(|h| norm_key(h) == norm_key(name));
+    let key = find(&config.table_key_col)?;
+    let value = if config.table_value_col.is_empty() {
+        key + 1
+    } else {
+        find(&config.table_value_col)?
+    };
+    (value < header.len() && value != key).then_some((key, value))
+}
+
+/// Boolean vocabulary used by `bool_compress`, keyed by lowercase word.
+///
+/// A non-empty `config.bool_map` replaces the built-in yes/no, true/false and
+/// enabled/disabled words, or adds to them when `bool_map_extend` is set.
+pub fn bool_map(config: &Config) -> HashMap<String, String> {
     let mut m = HashMap::new();
-    m.insert("yes", "Y");
-    m.insert("no", "N");
-    m.insert("true", "T");
-    m.insert("false", "F");
-    m.insert("enabled", "Y");
-    m.insert("disabled", "N");
+    if config.bool_map.is_empty() || config.bool_map_extend {
+        m.insert("yes".to_string(), "Y".to_string());
+        m.insert("no".to_string(), "N".to_string());
+        m.insert("true".to_string(), "T".to_string());
+        m.insert("false".to_string(), "F".to_string());
+        m.insert("enabled".to_string(), "Y".to_string());
+        m.insert("disabled".to_string(), "N".to_string());
+    }
+    for (word, short) in &config.bool_map {
+        m.insert(word.to_lowercase(), short.clone());
+    }
     m
 }
 
-fn compress_bool_value(val: &str, enabled: bool) -> String {
+fn compress_bool_value(val: &str, enabled: bool, bm: &HashMap<String, String>) -> String {
     if !enabled {
         return val.to_string();
     }
-    let bm = bool_map();
     let low = val.trim().to_lowercase();
     bm.get(low.as_str())
         .map(|s| s.to_string())
         .unwrap_or_else(|| val.to_string())
 }
 
-fn split_sentences(text: &str, sentence_split: bool, compression: i32) -> Vec<String> {
-    if !sentence_split || compression < 2 {
+fn split_sentences(text: &str, config: &Config) -> Vec<String> {
+    if !config.sentence_split || config.compression < 2 {
         return vec![text.to_string()];
     }
     // Use fancy_regex for lookbehind
-    let parts: Vec<String> = RE_SENTENCE_SPLIT
-        .split(text)
-        .filter_map(|r| r.ok())
-        .map(|s| s.to_string())
-        .filter(|s| !s.trim().is_empty())
-        .collect();
+    let (re, abbreviations) = match config.sentence_split_next {
+        SentenceSplitNext::Upper => (&*RE_SENTENCE_SPLIT, &[][..]),
+        SentenceSplitNext::Any => (&*RE_SENTENCE_SPLIT_ANY, ABBREVIATIONS),
+    };
+    let mut parts: Vec<String> = Vec::new();
+    let mut start = 0;
+    for m in re.find_iter(text).filter_map(|r| r.ok()) {
+        let before = &text[start..m.start()];
+        let last_word = before.rsplit(char::is_whitespace).next().unwrap_or("");
+        if abbreviations.iter().any(|a| last_word.eq_ignore_ascii_case(a)) {
+            continue;
+        }
+        parts.push(before.to_string());
+        start = m.end();
+    }
+    parts.push(text[start..].to_string());
+    let parts: Vec<String> = parts.into_iter().filter(|s| !s.trim().is_empty()).collect();
     if parts.is_empty() {
         vec![text.to_string()]
     } else {
@@ -99,6 +147,31 @@ fn split_sentences(text: &str, sentence_split: bool, compression: i32) -> Vec<St
     }
 }
 
+/// Join keyed-multi cells with `sep`, backslash-escaping `sep` inside a cell.
+fn join_cells(cells: &[String], sep: &str) -> String {
+    let escaped: Vec<String> = cells.iter().map(|c| c.replace(sep, &format!("\\{}", sep))).collect();
+    escaped.join(sep)
+}
+
+/// Quote a CSV field per RFC 4180 when it contains a comma, quote or line break.
+fn csv_field(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Collapse whitespace in block content line by line, dropping blank lines.
+fn normalize_code_whitespace(content: &str) -> String {
+    content
+        .lines()
+        .map(|l| RE_WHITESPACE.replace_all(l, " ").trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 fn find_common_prefix(keys: &[String]) -> String {
     if keys.len() < 2 {
         return String::new();
@@ -134,30 +207,389 @@ fn find_common_prefix(keys: &[String]) -> String {
     prefix
 }
 
+/// Render a depth-0 bullet, escaping text that would read as a depth or continuation marker.
+fn bullet(text: &str) -> String {
+    let escape = if text.starts_with(['.', '+']) { "\\" } else { "" };
+    format!("-{}{}", escape, text)
+}
+
+/// The texts of the list starting at `ir[0]` when it qualifies for
+/// `inline_short_lists`: 2 to `max_items` top-level bullet or numbered items
+/// with no nested items or continuation paragraphs.
+fn short_list_texts(ir: &[IrNode], max_items: usize) -> Option<Vec<&str>> {
+    let mut texts = Vec::new();
+    for node in ir {
+        let IrNode::ListItem {
+            depth,
+            text,
+            style,
+            paragraphs,
+            ..
+        } = node
+        else {
+            break;
+        };
+        if *depth > 0 || !paragraphs.is_empty() || !matches!(style, ListStyle::Bullet | ListStyle::Decimal) {
+            return None;
+        }
+        texts.push(text.as_str());
+    }
+    (2..=max_items).contains(&texts.len()).then_some(texts)
+}
+
+/// Rewrite emitted body lines as `-` bullets for `uniform_bullets`: KV pairs
+/// become `-key: value` (one per pair, with any `:_pfx` prefix restored), table
+/// headers and rows become `-a | b`, and text lines gain a `-`. Scopes, list
+/// items, code blocks and other LLMD lines are left as they are.
+fn uniform_bullet_lines(lines: Vec<String>) -> Vec<String> {
+    let cells = |row: &str| row.replace('¦', " | ");
+    let mut out = Vec::with_capacity(lines.len());
+    let mut prefix = String::new();
+    let mut in_block = false;
+    for line in lines {
+        if line == "<<<" || line == ">>>" {
+            in_block = line == "<<<";
+            out.push(line);
+            continue;
+        }
+        if in_block {
+            out.push(line);
+            continue;
+        }
+        if !line.starts_with(':') || line.starts_with("::") {
+            prefix.clear();
+        }
+        if let Some(p) = line.strip_prefix(":_pfx=") {
+            prefix = p.to_string();
+        } else if let Some(header) = line.strip_prefix(":_cols=").or_else(|| line.strip_prefix(":_col=")) {
+            prefix.clear();
+            out.push(bullet(&cells(header)));
+        } else if let Some(pairs) = crate::reader::parse_kv_pairs(&line) {
+            for (k, v) in pairs {
+                out.push(bullet(&format!("{}{}: {}", prefix, k, cells(&v))));
+            }
+        } else if line.is_empty() || line.starts_with(['@', ':', '-', '~', '=', '\u{2192}', '\u{2190}']) {
+            out.push(line);
+        } else {
+            out.push(bullet(&cells(&line)));
+        }
+    }
+    out
+}
+
+/// Short stable id for `scope_ids`: a hash of the heading path (normalized
+/// names of the heading and its ancestors) and how many times that path has
+/// occurred so far, so it depends on names and position but not on content.
+fn scope_id(stack: &[(usize, String)], path_counts: &mut HashMap<String, usize>) -> String {
+    let path: Vec<&str> = stack.iter().map(|(_, name)| name.as_str()).collect();
+    let path = path.join("/");
+    let count = path_counts.entry(path.clone()).or_insert(0);
+    *count += 1;
+    crate::postprocess::checksum(&format!("{}:{}", path, count))[..6].to_string()
+}
+
+/// The text of a short paragraph wholly in `*`/`_` emphasis (`*Table 1: Limits*`).
+fn caption_text(text: &str) -> Option<&str> {
+    ["**", "__", "*", "_"].iter().find_map(|m| {
+        let inner = text.strip_prefix(m)?.strip_suffix(m)?;
+        let ok = !inner.trim().is_empty() && !inner.contains(['*', '_']) && inner.split_whitespace().count() <= 12;
+        ok.then_some(inner.trim())
+    })
+}
+
+/// Captions for `associate_table_captions`, keyed by table index with the index
+/// of the caption paragraph: an emphasized line right before the table (blank
+/// lines aside), or else right after it.
+fn table_captions(ir: &[IrNode]) -> HashMap<usize, (usize, &str)> {
+    let caption_at = |i: usize| match &ir[i] {
+        IrNode::Paragraph { text, .. } => caption_text(text),
+        _ => None,
+    };
+    let mut captions: HashMap<usize, (usize, &str)> = HashMap::new();
+    let mut claimed: HashSet<usize> = HashSet::new();
+    for (idx, node) in ir.iter().enumerate() {
+        if !matches!(node, IrNode::Table { .. }) {
+            continue;
+        }
+        let before = (0..idx).rev().find(|&i| !matches!(ir[i], IrNode::Blank { .. }));
+        let after = (idx + 1..ir.len()).find(|&i| !matches!(ir[i], IrNode::Blank { .. }));
+        let found = [before, after]
+            .into_iter()
+            .flatten()
+            .filter(|i| !claimed.contains(i))
+            .find_map(|i| caption_at(i).map(|text| (i, text)));
+        if let Some((i, text)) = found {
+            claimed.insert(i);
+            captions.insert(idx, (i, text));
+        }
+    }
+    captions
+}
+
+/// Remap the heading levels used in a document to a dense 1..N sequence,
+/// so skipped levels (H1 then H3) don't leave gaps.
+fn normalize_heading_levels(ir: &[IrNode]) -> Vec<IrNode> {
+    let mut levels: Vec<usize> = ir
+        .iter()
+        .filter_map(|n| match n {
+            IrNode::Heading { level, .. } => Some(*level),
+            _ => None,
+        })
+        .collect();
+    levels.sort_unstable();
+    levels.dedup();
+    ir.iter()
+        .map(|n| match n {
+            IrNode::Heading { level, text, anchor, line } => IrNode::Heading {
+                level: levels.binary_search(level).unwrap() + 1,
+                text: text.clone(),
+                anchor: anchor.clone(),
+                line: *line,
+            },
+            other => other.clone(),
+        })
+        .collect()
+}
+
+/// Indices of headings with no body of their own and exactly one direct child heading.
+fn single_child_parents(ir: &[IrNode]) -> HashSet<usize> {
+    let mut out = HashSet::new();
+    for (i, node) in ir.iter().enumerate() {
+        let IrNode::Heading { level, .. } = node else {
+            continue;
+        };
+        let mut rest = ir[i + 1..]
+            .iter()
+            .filter(|n| !matches!(n, IrNode::Blank { .. }));
+        let child_level = match rest.next() {
+            Some(IrNode::Heading { level: c, .. }) if c > level => *c,
+            _ => continue,
+        };
+        let mut children = 1;
+        for n in rest {
+            if let IrNode::Heading { level: l, .. } = n {
+                if l <= level {
+                    break;
+                }
+                if *l <= child_level {
+                    children += 1;
+                }
+            }
+        }
+        if children == 1 {
+            out.insert(i);
+        }
+    }
+    out
+}
+
+/// Tracks the open @scope while emitting and records where each one came from.
+struct ScopeState {
+    current: Option<String>,
+    /// Defer each `@scope` line until its first body line (omit_empty_scopes)
+    defer: bool,
+    /// Scope for content before the first heading
+    lead: String,
+    /// Source lines before the first heading
+    lead_lines: [usize; 2],
+    /// Scope, heading and source lines waiting for their first body line
+    pending: Option<(String, String, [usize; 2])>,
+    /// Output index just after the current @scope line
+    body_start: usize,
+    origins: Vec<ScopeOrigin>,
+}
+
+impl ScopeState {
+    /// Open a scope. When deferring, its `@scope` line waits until `ensure`
+    /// sees body content, so a heading with nothing under it emits nothing.
+    /// A heading that continues the open scope extends its source lines instead.
+    fn emit(&mut self, scope: &str, heading: &str, lines: [usize; 2], out: &mut Vec<String>) {
+        if scope.is_empty() {
+            return;
+        }
+        if self.current.as_deref() == Some(scope) {
+            if let Some(origin) = self.origins.last_mut() {
+                origin.source_lines[1] = origin.source_lines[1].max(lines[1]);
+            }
+            self.pending = None;
+            return;
+        }
+        if self.defer {
+            self.pending = Some((scope.to_string(), heading.to_string(), lines));
+        } else {
+            self.push(scope, heading, lines, out);
+        }
+    }
+
+    fn push(&mut self, scope: &str, heading: &str, lines: [usize; 2], out: &mut Vec<String>) {
+        out.push(format!("@{}", scope));
+        self.current = Some(scope.to_string());
+        self.body_start = out.len();
+        self.origins.push(ScopeOrigin {
+            scope: scope.to_string(),
+            heading: heading.to_string(),
+            source_lines: lines,
+        });
+    }
+
+    /// Write the pending `@scope` line (or the lead scope if none was opened
+    /// yet) before a body line.
+    fn ensure(&mut self, out: &mut Vec<String>) {
+        if let Some((scope, heading, lines)) = self.pending.take() {
+            self.push(&scope, &heading, lines, out);
+        } else if self.current.is_none() {
+            let lead = self.lead.clone();
+            self.push(&lead, "", self.lead_lines, out);
+        }
+    }
+}
+
+/// Emitted lines plus the origin of every @scope line, in output order.
+pub struct Emitted {
+    pub lines: Vec<String>,
+    pub scopes: Vec<ScopeOrigin>,
+}
+
 struct KvPair {
     key: String,
     value: String,
 }
 
-pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], config: &Config) -> Vec<String> {
+/// Split KV pairs into lines of at most `max` pairs. With `stable`, a line never
+/// spans two key groups (runs of keys sharing the part before the first `_`, `.`
+/// or `-`), so adding a key only reflows its own group's lines.
+fn kv_chunks(pairs: &[KvPair], max: usize, stable: bool) -> Vec<&[KvPair]> {
+    if !stable {
+        return pairs.chunks(max).collect();
+    }
+    let group = |kv: &KvPair| kv.key.split(['_', '.', '-']).next().unwrap_or("").to_string();
+    let mut chunks: Vec<&[KvPair]> = Vec::new();
+    let mut start = 0;
+    while start < pairs.len() {
+        let g = group(&pairs[start]);
+        let len = pairs[start..].iter().take_while(|kv| group(kv) == g).count();
+        chunks.extend(pairs[start..start + len].chunks(max));
+        start += len;
+    }
+    chunks
+}
+
+/// Emit LLMD lines, reading span-backed block content from `source`.
+pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], source: &str, config: &Config) -> Vec<String> {
+    emit_llmd_traced(ir, blocks, source, config).lines
+}
+
+/// Like [`emit_llmd`], also recording where each emitted scope came from.
+pub fn emit_llmd_traced(ir: &[IrNode], blocks: &[CodeBlock], source: &str, config: &Config) -> Emitted {
     let compression = config.compression;
     let keep_urls = config.keep_urls;
-    let sentence_split = config.sentence_split;
+    let url_domains = config.stripped_url_placeholder;
+    let keep_images = config.keep_images;
     let bool_compress_enabled = config.bool_compress && compression >= 2;
-    let max_kv_per_line = config.max_kv_per_line;
+    // `chunks(0)` panics; an unvalidated 0 means one pair per line
+    let max_kv_per_line = config.max_kv_per_line.max(1);
     let prefix_extraction = config.prefix_extraction;
     let min_prefix_len = config.min_prefix_len;
     let min_prefix_pct = config.min_prefix_pct;
+    let min_suffix_len = config.prefix_min_suffix_len.max(1);
+
+    let bm = bool_map(config);
+    let compress_code_langs: HashSet<String> = config
+        .compress_code_langs
+        .iter()
+        .map(|l| l.to_lowercase())
+        .collect();
+    // norm_scope_name lowercases from c2 on; scope_case can pin that either way
+    let scope_level = match config.scope_case {
+        ScopeCase::Level => compression,
+        ScopeCase::Source => 0,
+        ScopeCase::Lower => 2,
+    };
+    // Heading text as seen by scope normalization, romanized under transliterate_scopes
+    let scope_text = |text: &str| -> String {
+        if config.transliterate_scopes {
+            deunicode(text)
+        } else {
+            text.to_string()
+        }
+    };
+    let drop_scopes: HashSet<String> = config
+        .drop_scopes
+        .iter()
+        .map(|name| norm_scope_name(&scope_text(name), 2))
+        .collect();
+    // What a heading's scope name is derived from: its `{#id}` under preserve_anchors, else its text
+    let heading_scope_text = |text: &str, anchor: &Option<String>| -> String {
+        match anchor {
+            Some(id) if config.preserve_anchors => id.clone(),
+            _ => scope_text(text),
+        }
+    };
+    let bool_exclude: HashSet<String> = config
+        .bool_compress_exclude_cols
+        .iter()
+        .map(|c| norm_key(c))
+        .collect();
 
-    let bm = bool_map();
+    // Last source line of the section under each heading: the line before the next heading
+    let last_line = ir
+        .iter()
+        .filter(|n| !matches!(n, IrNode::Blank { .. }))
+        .map(IrNode::line)
+        .max()
+        .unwrap_or(0)
+        .max(source.trim_end().lines().count())
+        .max(1);
+    let mut section_ends = vec![last_line; ir.len()];
+    let mut next_end = last_line;
+    for (i, node) in ir.iter().enumerate().rev() {
+        section_ends[i] = next_end;
+        if matches!(node, IrNode::Heading { .. }) {
+            next_end = node.line().saturating_sub(1).max(1);
+        }
+    }
 
     let mut out: Vec<String> = Vec::new();
-    let mut current_scope: Option<String> = None;
+    let mut scope = ScopeState {
+        current: None,
+        defer: config.omit_empty_scopes,
+        lead: match norm_scope_name(&config.lead_scope_name, scope_level) {
+            name if name.is_empty() => "root".to_string(),
+            name => name,
+        },
+        lead_lines: [1, next_end],
+        pending: None,
+        body_start: 0,
+        origins: Vec::new(),
+    };
     let mut heading_stack: Vec<(usize, String)> = Vec::new();
     let mut kv_buffer: Vec<KvPair> = Vec::new();
+    // Body start of the scope whose summary paragraph was already hoisted
+    let mut hoisted_at: Option<usize> = None;
+    // Level of a dropped heading whose subtree is being skipped
+    let mut dropping: Option<usize> = None;
+    // End of a short list already emitted on one line, for inline_short_lists
+    let mut inlined_until = 0;
+    // Position among the emitted `<<<` blocks (code and CSV tables alike) of the
+    // first one with each content, for dedup_code
+    let mut emitted_blocks: HashMap<&str, usize> = HashMap::new();
+    let mut block_count = 0;
+    // A ref would point at the wrong block once scopes are filtered, reordered or truncated
+    let dedup_code =
+        config.dedup_code && config.only_scopes.is_empty() && !config.sort_scopes && config.max_tokens == 0;
+    // Times each heading path has been seen, for scope_ids
+    let mut path_counts: HashMap<String, usize> = HashMap::new();
+    // Times each heading scope name has been emitted, for distinguish_repeated_headings
+    let mut seen_scopes: HashMap<String, usize> = HashMap::new();
 
-    let resolve_scope = |level: usize, text: &str, stack: &mut Vec<(usize, String)>| -> String {
-        let name = norm_scope_name(text, compression);
+    // Headings whose transliterated name came out empty, for @section-N
+    let mut untitled = 0;
+    let mut resolve_scope = |level: usize, text: &str, stack: &mut Vec<(usize, String)>| -> String {
+        let mut name = norm_scope_name(text, scope_level);
+        if name.is_empty() && config.transliterate_scopes {
+            untitled += 1;
+            name = format!("section-{}", untitled);
+        }
         while !stack.is_empty() && stack.last().unwrap().0 >= level {
             stack.pop();
         }
@@ -170,27 +602,35 @@ pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], config: &Config) -> Vec<St
         }
     };
 
-    let emit_scope =
-        |scope: &str, current: &mut Option<String>, out: &mut Vec<String>| {
-            if !scope.is_empty() && current.as_deref() != Some(scope) {
-                out.push(format!("@{}", scope));
-                *current = Some(scope.to_string());
-            }
-        };
+    let decode_entities = config.decode_html_entities && compression >= 2;
 
-    let ensure_scope = |current: &mut Option<String>, out: &mut Vec<String>| {
-        if current.is_none() {
-            out.push("@root".to_string());
-            *current = Some("root".to_string());
+    let process_text = |text: &str| -> String {
+        let stripped;
+        let text = if config.strip_html {
+            stripped = strip_html(text);
+            stripped.as_str()
+        } else {
+            text
+        };
+        let linked;
+        let text = if config.rewrite_internal_links {
+            linked = internal_link_refs(text);
+            linked.as_str()
+        } else {
+            text
+        };
+        if decode_entities {
+            let decoded = decode_html_entities_literal(text);
+            process_inline_with(&decoded, compression, keep_urls, url_domains, keep_images, &config.keep_emphasis)
+        } else {
+            process_inline_with(text, compression, keep_urls, url_domains, keep_images, &config.keep_emphasis)
         }
     };
 
-    let process_text = |text: &str| -> String { process_inline(text, compression, keep_urls) };
-
     let process_cell = |cell: &str, col_idx: usize, bool_cols: &HashSet<usize>| -> String {
         let text = process_text(cell);
         if bool_cols.contains(&col_idx) {
-            compress_bool_value(&text, bool_compress_enabled)
+            compress_bool_value(&text, bool_compress_enabled, &bm)
         } else {
             text
         }
@@ -201,11 +641,31 @@ pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], config: &Config) -> Vec<St
             return;
         }
 
+        // Fold repeated keys into one list value, in first-seen key order
+        if config.merge_repeated_kv {
+            let mut merged: Vec<KvPair> = Vec::new();
+            for kv in kv_buffer.drain(..) {
+                match merged.iter_mut().find(|m| m.key == kv.key) {
+                    Some(m) => {
+                        m.value.push_str(&config.kv_list_separator);
+                        m.value.push_str(&kv.value);
+                    }
+                    None => merged.push(kv),
+                }
+            }
+            *kv_buffer = merged;
+        }
+
         // Try prefix extraction at c1+
         if compression >= 1 && prefix_extraction && kv_buffer.len() >= 3 {
             let keys: Vec<String> = kv_buffer.iter().map(|kv| kv.key.clone()).collect();
             let prefix = find_common_prefix(&keys);
-            if prefix.len() >= min_prefix_len {
+            // Every shortened key must keep a real name after the prefix
+            let suffixes_ok = keys
+                .iter()
+                .filter(|k| k.starts_with(&prefix))
+                .all(|k| crate::util::grapheme_len(&k[prefix.len()..]) >= min_suffix_len);
+            if prefix.len() >= min_prefix_len && suffixes_ok {
                 let match_count = keys.iter().filter(|k| k.starts_with(&prefix)).count();
                 if match_count as f64 / keys.len() as f64 >= min_prefix_pct {
                     out.push(format!(":_pfx={}", prefix));
@@ -223,7 +683,7 @@ pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], config: &Config) -> Vec<St
                             }
                         })
                         .collect();
-                    for chunk in adjusted.chunks(max_kv_per_line) {
+                    for chunk in kv_chunks(&adjusted, max_kv_per_line, config.stable_kv_chunks) {
                         let pairs: Vec<String> = chunk
                             .iter()
                             .map(|kv| format!("{}={}", kv.key, kv.value))
@@ -236,7 +696,7 @@ pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], config: &Config) -> Vec<St
         }
 
         if compression >= 1 {
-            for chunk in kv_buffer.chunks(max_kv_per_line) {
+            for chunk in kv_chunks(kv_buffer, max_kv_per_line, config.stable_kv_chunks) {
                 let pairs: Vec<String> = chunk
                     .iter()
                     .map(|kv| format!("{}={}", kv.key, kv.value))
@@ -251,55 +711,203 @@ pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], config: &Config) -> Vec<St
         kv_buffer.clear();
     };
 
-    for node in ir {
+    let normalized;
+    let ir = if config.normalize_heading_levels {
+        normalized = normalize_heading_levels(ir);
+        &normalized[..]
+    } else {
+        ir
+    };
+
+    let flatten = if config.flatten_single_child_scopes {
+        single_child_parents(ir)
+    } else {
+        HashSet::new()
+    };
+    let captions = if config.associate_table_captions {
+        table_captions(ir)
+    } else {
+        HashMap::new()
+    };
+    let caption_paragraphs: HashSet<usize> = captions.values().map(|(i, _)| *i).collect();
+
+    for (idx, node) in ir.iter().enumerate() {
         if !matches!(node, IrNode::Kv { .. }) {
             flush_kv(&mut kv_buffer, &mut out);
         }
 
+        if let IrNode::Heading { level, text, anchor, .. } = node {
+            if dropping.is_some_and(|d| *level <= d) {
+                dropping = None;
+            }
+            if dropping.is_none() && drop_scopes.contains(&norm_scope_name(&heading_scope_text(text, anchor), 2)) {
+                dropping = Some(*level);
+            }
+        }
+        if dropping.is_some() {
+            continue;
+        }
+
         match node {
-            IrNode::Heading { level, text } => {
-                let scope = resolve_scope(*level, text, &mut heading_stack);
-                emit_scope(&scope, &mut current_scope, &mut out);
+            IrNode::Heading { level, text, anchor, line } => {
+                let mut name = resolve_scope(*level, &heading_scope_text(text, anchor), &mut heading_stack);
+                // An empty parent is folded into its only child's scope
+                if flatten.contains(&idx) {
+                    continue;
+                }
+                if config.distinguish_repeated_headings && !name.is_empty() {
+                    let count = seen_scopes.entry(name.clone()).or_insert(0);
+                    *count += 1;
+                    if *count > 1 {
+                        name = format!("{}-{}", name, count);
+                    }
+                }
+                if config.emit_scope_level && !name.is_empty() {
+                    name = format!("{}#{}", name, level);
+                }
+                if config.scope_ids && !name.is_empty() {
+                    name = format!("{}#{}", name, scope_id(&heading_stack, &mut path_counts));
+                }
+                scope.emit(&name, text, [*line, section_ends[idx].max(*line)], &mut out);
             }
-            IrNode::Paragraph { text } => {
-                ensure_scope(&mut current_scope, &mut out);
+            IrNode::Paragraph { .. } if caption_paragraphs.contains(&idx) => {}
+            IrNode::Paragraph { text, .. } => {
+                scope.ensure(&mut out);
                 let text = process_text(text);
-                let sentences = split_sentences(&text, sentence_split, compression);
-                for s in sentences {
-                    let s = s.trim();
-                    if !s.is_empty() {
-                        out.push(s.to_string());
-                    }
+                let sentences = split_sentences(&text, config);
+                let lines: Vec<String> = sentences
+                    .iter()
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.to_string())
+                    .collect();
+                if config.hoist_summary && hoisted_at != Some(scope.body_start) {
+                    // First paragraph of the scope leads its body
+                    hoisted_at = Some(scope.body_start);
+                    out.splice(scope.body_start..scope.body_start, lines);
+                } else {
+                    out.extend(lines);
                 }
             }
-            IrNode::ListItem { depth, text, .. } => {
-                ensure_scope(&mut current_scope, &mut out);
+            IrNode::Admonition { kind, text, .. } => {
+                scope.ensure(&mut out);
+                out.push(format!("~{}", kind));
                 let text = process_text(text);
+                out.extend(
+                    split_sentences(&text, config)
+                        .iter()
+                        .map(|s| s.trim())
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.to_string()),
+                );
+            }
+            IrNode::ListItem {
+                depth,
+                text,
+                style,
+                marker,
+                paragraphs,
+                ..
+            } => {
+                if idx < inlined_until {
+                    continue;
+                }
+                scope.ensure(&mut out);
+                let list_start = idx == 0 || !matches!(ir[idx - 1], IrNode::ListItem { .. });
+                if config.inline_short_lists > 0 && list_start {
+                    if let Some(texts) = short_list_texts(&ir[idx..], config.inline_short_lists) {
+                        let items: Vec<String> = texts.iter().map(|t| process_text(t)).collect();
+                        let max_tokens = config.inline_list_max_tokens;
+                        // An item with its own `;` would read as two items once joined
+                        let fits = |t: &String| t.split_whitespace().count() <= max_tokens && !t.contains(';');
+                        if items.iter().all(fits) {
+                            out.push(bullet(&items.join("; ")));
+                            inlined_until = idx + items.len();
+                            continue;
+                        }
+                    }
+                }
+                let text = match style {
+                    // Lettered and roman numbering carries meaning ("see clause b"), so keep it
+                    ListStyle::LowerAlpha | ListStyle::LowerRoman => format!("{}. {}", marker, process_text(text)),
+                    ListStyle::Bullet | ListStyle::Decimal => process_text(text),
+                };
                 let depth_dots = ".".repeat(*depth);
                 if depth_dots.is_empty() {
-                    out.push(format!("-{}", text));
+                    out.push(bullet(&text));
                 } else {
                     out.push(format!("-{} {}", depth_dots, text));
                 }
+                // Continuation paragraphs keep the item's depth, marked with `+`
+                for para in paragraphs {
+                    out.push(format!("-{}+ {}", depth_dots, process_text(para)));
+                }
             }
-            IrNode::Kv { key, value } => {
-                ensure_scope(&mut current_scope, &mut out);
+            IrNode::Kv { key, value, .. } => {
+                scope.ensure(&mut out);
                 let k = norm_key(key);
-                let v = process_text(value);
+                let mut v = process_text(value);
+                let code_like = {
+                    let raw = value.trim();
+                    raw.len() > 1 && raw.starts_with('`') && raw.ends_with('`')
+                };
+                if config.normalize_kv_whitespace && !code_like {
+                    v = RE_WHITESPACE.replace_all(v.trim(), " ").to_string();
+                }
+                // `id | name | email` is a column list: keep the pipes, drop their padding
+                if config.compact_pipe_lists && !code_like {
+                    let parts: Vec<&str> = v.split('|').map(str::trim).collect();
+                    if parts.len() > 1 && parts.iter().all(|p| !p.is_empty()) {
+                        v = parts.join("|");
+                    }
+                }
                 if !k.is_empty() {
                     kv_buffer.push(KvPair { key: k, value: v });
                 } else {
                     out.push(process_text(&format!("{}: {}", key, value)));
                 }
             }
-            IrNode::Table { rows } => {
-                ensure_scope(&mut current_scope, &mut out);
-                let table_type = classify_table(rows);
+            IrNode::Table { rows, depth, .. } => {
+                scope.ensure(&mut out);
+                if let Some((_, caption)) = captions.get(&idx) {
+                    out.push(format!(":_caption={}", process_text(caption)));
+                }
+
+                // Emit the table verbatim as a CSV block, one row per line
+                if config.table_mode == TableMode::Csv {
+                    out.push("::csv".to_string());
+                    out.push("<<<".to_string());
+                    block_count += 1;
+                    for r in rows {
+                        let fields: Vec<String> = r.iter().map(|c| csv_field(c)).collect();
+                        out.push(fields.join(","));
+                    }
+                    out.push(">>>".to_string());
+                    continue;
+                }
+                // A table with designated key/value columns reads as a property table
+                // of just those two, when they classify as one
+                let projected: Vec<Vec<String>>;
+                let designated = property_columns(&rows[0], config)
+                    .filter(|_| *depth == 0 && rows.iter().all(|r| r.len() == rows[0].len()));
+                let (rows, table_type) = match designated {
+                    Some((k, v)) => {
+                        projected = rows.iter().map(|r| vec![r[k].clone(), r[v].clone()]).collect();
+                        match classify_table(&projected, config.allow_numeric_keys) {
+                            "property" => (&projected, "property"),
+                            _ => (rows, classify_table(rows, config.allow_numeric_keys)),
+                        }
+                    }
+                    None => (rows, classify_table(rows, config.allow_numeric_keys)),
+                };
 
                 // Detect boolean columns for compression
                 let mut bool_cols: HashSet<usize> = HashSet::new();
                 if bool_compress_enabled && rows.len() > 1 {
                     for c in 1..rows[0].len() {
+                        if bool_exclude.contains(&norm_key(&rows[0][c])) {
+                            continue;
+                        }
                         let all_bool = rows[1..].iter().all(|r| {
                             let val = r.get(c).map_or("", |s| s.as_str()).trim().to_lowercase();
                             bm.contains_key(val.as_str())
@@ -310,6 +918,26 @@ pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], config: &Config) -> Vec<St
                     }
                 }
 
+                // Tables nested under a list item stay inside it as deeper items
+                if *depth > 0 {
+                    let depth_dots = ".".repeat(*depth);
+                    for (ri, r) in rows.iter().enumerate() {
+                        let cells: Vec<String> = r
+                            .iter()
+                            .enumerate()
+                            .map(|(ci, c)| {
+                                if ri == 0 {
+                                    process_text(c)
+                                } else {
+                                    process_cell(c, ci, &bool_cols)
+                                }
+                            })
+                            .collect();
+                        out.push(format!("-{} {}", depth_dots, cells.join("¦")));
+                    }
+                    continue;
+                }
+
                 match table_type {
                     "property" => {
                         // Emit column header if informative
@@ -331,10 +959,23 @@ pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], config: &Config) -> Vec<St
                             }
                         }
                     }
+                    "keyed_multi"
+                        if config.collapse_single_row_tables
+                            && rows.len() == 2
+                            && rows[0].iter().all(|h| !norm_key(h).is_empty()) =>
+                    {
+                        for (ci, (h, c)) in rows[0].iter().zip(&rows[1]).enumerate() {
+                            kv_buffer.push(KvPair {
+                                key: norm_key(h),
+                                value: process_cell(c, ci, &bool_cols),
+                            });
+                        }
+                    }
                     "keyed_multi" => {
+                        let sep = config.multi_value_separator.as_str();
                         let col_headers: Vec<String> =
                             rows[0].iter().map(|h| norm_key(h)).collect();
-                        out.push(format!(":_cols={}", col_headers.join("¦")));
+                        out.push(format!(":_cols={}", join_cells(&col_headers, sep)));
                         for r in &rows[1..] {
                             let k = norm_key(&r[0]);
                             let vals: Vec<String> = r[1..]
@@ -345,7 +986,7 @@ pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], config: &Config) -> Vec<St
                             if !k.is_empty() {
                                 kv_buffer.push(KvPair {
                                     key: k,
-                                    value: vals.join("¦"),
+                                    value: join_cells(&vals, sep),
                                 });
                             } else {
                                 let cells: Vec<String> = r
@@ -353,7 +994,7 @@ pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], config: &Config) -> Vec<St
                                     .enumerate()
                                     .map(|(ci, c)| process_cell(c, ci, &bool_cols))
                                     .collect();
-                                out.push(cells.join("¦"));
+                                out.push(join_cells(&cells, sep));
                             }
                         }
                     }
@@ -375,24 +1016,57 @@ pub fn emit_llmd(ir: &[IrNode], blocks: &[CodeBlock], config: &Config) -> Vec<St
                     }
                 }
             }
-            IrNode::BlockRef { index } => {
-                ensure_scope(&mut current_scope, &mut out);
+            IrNode::BlockRef { index, .. } => {
+                scope.ensure(&mut out);
                 let block = &blocks[*index];
                 let lang = if block.lang.is_empty() {
                     "code"
                 } else {
                     &block.lang
                 };
+                if block.text(source).trim().is_empty() {
+                    out.push(format!("::{} (empty)", lang));
+                    continue;
+                }
+                if dedup_code {
+                    if let Some(first) = emitted_blocks.get(block.text(source)) {
+                        out.push(format!("::{} ref={}", lang, first));
+                        continue;
+                    }
+                    emitted_blocks.insert(block.text(source), block_count);
+                }
                 out.push(format!("::{}", lang));
                 out.push("<<<".to_string());
-                out.push(block.content.clone());
+                block_count += 1;
+                let transform = config
+                    .code_transforms
+                    .iter()
+                    .find(|(lang, _)| lang.eq_ignore_ascii_case(&block.lang))
+                    .map(|(_, t)| t.as_str());
+                let minified = match transform {
+                    Some("minify") => crate::blocks::minify_json(block.text(source)),
+                    _ => None,
+                };
+                if let Some(minified) = minified {
+                    out.push(minified);
+                } else if compress_code_langs.contains(&block.lang.to_lowercase()) {
+                    out.push(normalize_code_whitespace(block.text(source)));
+                } else {
+                    out.push(block.text(source).to_string());
+                }
                 out.push(">>>".to_string());
             }
-            IrNode::Blank => {}
+            IrNode::Blank { .. } => {}
         }
     }
     flush_kv(&mut kv_buffer, &mut out);
-    out
+    if config.uniform_bullets {
+        out = uniform_bullet_lines(out);
+    }
+    Emitted {
+        lines: out,
+        scopes: scope.origins,
+    }
 }
 
 #[cfg(test)]
@@ -405,160 +1079,1206 @@ mod tests {
             IrNode::Heading {
                 level: 1,
                 text: "Title".to_string(),
+                anchor: None,
+                line: 0,
             },
             IrNode::Paragraph {
                 text: "content".to_string(),
+                line: 0,
             },
         ];
         let config = Config::default();
-        let result = emit_llmd(&ir, &[], &config);
+        let result = emit_llmd(&ir, &[], "", &config);
         assert_eq!(result[0], "@title");
         assert_eq!(result[1], "content");
     }
 
     #[test]
-    fn test_root_scope_injection() {
-        let ir = vec![IrNode::Paragraph {
-            text: "orphan text".to_string(),
+    fn test_sentence_split_next() {
+        let text = "Retry done. 3 retries remain. see the log, e.g. Errors. Then stop.";
+        let mut config = Config {
+            sentence_split: true,
+            ..Default::default()
+        };
+        assert_eq!(split_sentences(text, &config), vec!["Retry done. 3 retries remain. see the log, e.g.", "Errors.", "Then stop."]);
+        config.sentence_split_next = SentenceSplitNext::Any;
+        assert_eq!(
+            split_sentences(text, &config),
+            vec!["Retry done.", "3 retries remain.", "see the log, e.g. Errors.", "Then stop."]
+        );
+        assert_eq!(split_sentences("Done. (Really.) ok", &config), vec!["Done.", "(Really.) ok"]);
+    }
+
+    #[test]
+    fn test_scope_ids_stable_across_compiles() {
+        let heading = |level: usize, text: &str| IrNode::Heading {
+            level,
+            text: text.to_string(),
+            anchor: None,
+            line: 0,
+        };
+        let ir = vec![heading(1, "Guide"), heading(2, "Setup"), heading(1, "Reference"), heading(2, "Setup")];
+        let config = Config {
+            scope_ids: true,
+            ..Default::default()
+        };
+        let first = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(first, emit_llmd(&ir, &[], "", &config));
+        let ids: Vec<&str> = first.iter().map(|l| l.split_once('#').unwrap().1).collect();
+        assert!(ids.iter().all(|id| id.len() == 6));
+        // Same name under different parents gets a different id
+        assert!(first[1].starts_with("@setup#") && first[3].starts_with("@setup#"));
+        assert_ne!(ids[1], ids[3]);
+        // Renaming a heading changes its id and its children's, but not its siblings'
+        let renamed = vec![heading(1, "Guide"), heading(2, "Setup"), heading(1, "API"), heading(2, "Setup")];
+        let second = emit_llmd(&renamed, &[], "", &config);
+        assert_eq!(second[..2], first[..2]);
+        assert_ne!(second[3], first[3]);
+    }
+
+    #[test]
+    fn test_prose_admonition() {
+        let ir = vec![IrNode::Admonition {
+            kind: "warn".to_string(),
+            text: "Back up first. Then upgrade.".to_string(),
+            line: 0,
         }];
-        let config = Config::default();
-        let result = emit_llmd(&ir, &[], &config);
-        assert_eq!(result[0], "@root");
-        assert_eq!(result[1], "orphan text");
+        let result = emit_llmd(&ir, &[], "", &Config::default());
+        assert_eq!(result, vec!["@root", "~warn", "Back up first. Then upgrade."]);
     }
 
     #[test]
-    fn test_kv_c0_separate() {
-        let ir = vec![
-            IrNode::Heading {
-                level: 1,
-                text: "S".to_string(),
-            },
-            IrNode::Kv {
-                key: "Key A".to_string(),
-                value: "1".to_string(),
-            },
-            IrNode::Kv {
-                key: "Key B".to_string(),
-                value: "2".to_string(),
-            },
-        ];
-        let mut config = Config::default();
-        config.compression = 0;
-        let result = emit_llmd(&ir, &[], &config);
-        assert!(result.contains(&":key_a=1".to_string()));
-        assert!(result.contains(&":key_b=2".to_string()));
+    fn test_table_key_and_value_columns() {
+        let ir = vec![IrNode::Table {
+            rows: vec![
+                vec!["#".to_string(), "Name".to_string(), "Value".to_string()],
+                vec!["1".to_string(), "timeout".to_string(), "30s".to_string()],
+                vec!["2".to_string(), "retries".to_string(), "yes".to_string()],
+            ],
+            depth: 0,
+            line: 0,
+        }];
+        let result = emit_llmd(&ir, &[], "", &Config::default());
+        assert_eq!(result, vec!["@root", ":_cols=¦name¦value", "1¦timeout¦30s", "2¦retries¦yes"]);
+        let config = Config {
+            table_key_col: "Name".to_string(),
+            table_value_col: "value".to_string(),
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@root", ":timeout=30s retries=yes"]);
+        let config = Config {
+            table_key_col: "name".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(emit_llmd(&ir, &[], "", &config), result);
     }
 
     #[test]
-    fn test_kv_c1_merged() {
-        let ir = vec![
-            IrNode::Heading {
-                level: 1,
-                text: "S".to_string(),
-            },
-            IrNode::Kv {
-                key: "A".to_string(),
-                value: "1".to_string(),
-            },
-            IrNode::Kv {
-                key: "B".to_string(),
-                value: "2".to_string(),
-            },
-        ];
-        let mut config = Config::default();
-        config.compression = 1;
-        let result = emit_llmd(&ir, &[], &config);
-        assert!(result.contains(&":a=1 b=2".to_string()));
+    fn test_multi_value_separator() {
+        let ir = vec![IrNode::Table {
+            rows: vec![
+                vec!["Name".to_string(), "Type".to_string(), "Notes".to_string()],
+                vec!["id".to_string(), "int".to_string(), "a¦b".to_string()],
+                vec!["tag".to_string(), "str".to_string(), "x | y".to_string()],
+            ],
+            depth: 0,
+            line: 0,
+        }];
+        let result = emit_llmd(&ir, &[], "", &Config::default());
+        assert_eq!(result, vec!["@root", ":_cols=name¦type¦notes", ":id=int¦a\\¦b tag=str¦x | y"]);
+        let config = Config {
+            multi_value_separator: "|".to_string(),
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@root", ":_cols=name|type|notes", ":id=int|a¦b tag=str|x \\| y"]);
     }
 
     #[test]
-    fn test_property_table() {
+    fn test_lead_scope_name() {
         let ir = vec![
+            IrNode::Paragraph {
+                text: "An abstract.".to_string(),
+                line: 0,
+            },
             IrNode::Heading {
                 level: 1,
-                text: "S".to_string(),
+                text: "Guide".to_string(),
+                anchor: None,
+                line: 0,
             },
-            IrNode::Table {
-                rows: vec![
-                    vec!["Name".to_string(), "Value".to_string()],
-                    vec!["key1".to_string(), "val1".to_string()],
-                    vec!["key2".to_string(), "val2".to_string()],
-                ],
+            IrNode::Paragraph {
+                text: "Body.".to_string(),
+                line: 0,
             },
         ];
-        let config = Config::default();
-        let result = emit_llmd(&ir, &[], &config);
-        // "Value" is a generic header, should not emit :_col
-        assert!(result.contains(&":key1=val1 key2=val2".to_string()));
+        let config = Config {
+            lead_scope_name: "Summary".to_string(),
+            ..Default::default()
+        };
+        let emitted = emit_llmd_traced(&ir, &[], "", &config);
+        assert_eq!(emitted.lines, vec!["@summary", "An abstract.", "@guide", "Body."]);
+        assert_eq!(emitted.scopes[0].heading, "");
+        assert_eq!(emit_llmd(&ir, &[], "", &Config::default())[0], "@root");
     }
 
     #[test]
-    fn test_block_ref_emission() {
-        let ir = vec![
-            IrNode::Heading {
-                level: 1,
-                text: "S".to_string(),
-            },
-            IrNode::BlockRef { index: 0 },
-        ];
-        let blocks = vec![CodeBlock {
+    fn test_scope_case_across_levels() {
+        let ir = vec![IrNode::Heading {
+            level: 1,
+            text: "Hello World".to_string(),
+            anchor: None,
+            line: 0,
+        }];
+        let scope_at = |compression: i32, scope_case: ScopeCase| {
+            let config = Config {
+                compression,
+                scope_case,
+                ..Default::default()
+            };
+            emit_llmd(&ir, &[], "", &config)[0].clone()
+        };
+        for compression in 0..=2 {
+            assert_eq!(scope_at(compression, ScopeCase::Source), "@Hello_World");
+            assert_eq!(scope_at(compression, ScopeCase::Lower), "@hello_world");
+        }
+        assert_eq!(scope_at(0, ScopeCase::Level), "@Hello_World");
+        assert_eq!(scope_at(1, ScopeCase::Level), "@Hello_World");
+        assert_eq!(scope_at(2, ScopeCase::Level), "@hello_world");
+    }
+
+    #[test]
+    fn test_transliterate_scopes() {
+        let heading = |text: &str| IrNode::Heading {
+            level: 1,
+            text: text.to_string(),
+            anchor: None,
+            line: 0,
+        };
+        let ir = vec![heading("Überblick"), heading("日本語"), heading("¿?"), heading("Café Menü")];
+        assert_eq!(emit_llmd(&ir, &[], "", &Config::default()), vec!["@berblick", "@caf_men"]);
+        let config = Config {
+            transliterate_scopes: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            emit_llmd(&ir, &[], "", &config),
+            vec!["@uberblick", "@ri_ben_yu", "@section-1", "@cafe_menu"]
+        );
+    }
+
+    #[test]
+    fn test_omit_empty_scopes() {
+        let heading = |level: usize, text: &str| IrNode::Heading {
+            level,
+            text: text.to_string(),
+            anchor: None,
+            line: 0,
+        };
+        let para = |text: &str| IrNode::Paragraph {
+            text: text.to_string(),
+            line: 0,
+        };
+        let ir = vec![
+            heading(1, "Guide"),
+            heading(2, "Setup"),
+            heading(2, "Usage"),
+            para("run it"),
+            heading(2, "Notes"),
+        ];
+        let config = Config {
+            omit_empty_scopes: true,
+            ..Default::default()
+        };
+        let emitted = emit_llmd_traced(&ir, &[], "", &config);
+        assert_eq!(emitted.lines, vec!["@usage", "run it"]);
+        assert_eq!(emitted.scopes.len(), 1);
+        assert_eq!(emitted.scopes[0].heading, "Usage");
+
+        let result = emit_llmd(&ir, &[], "", &Config::default());
+        assert_eq!(result, vec!["@guide", "@setup", "@usage", "run it", "@notes"]);
+    }
+
+    #[test]
+    fn test_emit_scope_level() {
+        let heading = |level: usize, text: &str| IrNode::Heading {
+            level,
+            text: text.to_string(),
+            anchor: None,
+            line: 0,
+        };
+        let ir = vec![
+            heading(1, "Guide"),
+            heading(2, "Setup"),
+            heading(3, "Tokens"),
+            heading(2, "Usage"),
+        ];
+        let config = Config {
+            emit_scope_level: true,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@guide#1", "@setup#2", "@tokens#3", "@usage#2"]);
+    }
+
+    #[test]
+    fn test_flatten_single_child_scopes() {
+        let heading = |level: usize, text: &str| IrNode::Heading {
+            level,
+            text: text.to_string(),
+            anchor: None,
+            line: 0,
+        };
+        let para = |text: &str| IrNode::Paragraph {
+            text: text.to_string(),
+            line: 0,
+        };
+        let ir = vec![
+            heading(1, "Guide"),
+            IrNode::Blank { line: 0 },
+            heading(2, "Setup"),
+            para("Install it"),
+            heading(1, "Reference"),
+            heading(2, "Auth"),
+            para("Tokens"),
+            heading(2, "Limits"),
+            para("Quotas"),
+        ];
+        let config = Config {
+            scope_mode: ScopeMode::Concat,
+            flatten_single_child_scopes: true,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(
+            result,
+            vec![
+                "@guide_setup",
+                "Install it",
+                "@reference",
+                "@reference_auth",
+                "Tokens",
+                "@reference_limits",
+                "Quotas",
+            ]
+        );
+
+        let config = Config {
+            scope_mode: ScopeMode::Concat,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result[0], "@guide");
+    }
+
+    #[test]
+    fn test_distinguish_repeated_headings() {
+        let heading = |level: usize, text: &str| IrNode::Heading {
+            level,
+            text: text.to_string(),
+            anchor: None,
+            line: 0,
+        };
+        let kv = |key: &str, value: &str| IrNode::Kv {
+            key: key.to_string(),
+            value: value.to_string(),
+            line: 0,
+        };
+        let ir = vec![
+            heading(2, "Get user"),
+            heading(3, "Parameters"),
+            kv("id", "string"),
+            heading(2, "List users"),
+            heading(3, "Parameters"),
+            kv("limit", "int"),
+            heading(3, "Parameters"),
+            kv("offset", "int"),
+        ];
+        let result = emit_llmd(&ir, &[], "", &Config::default());
+        assert_eq!(
+            result,
+            vec!["@get_user", "@parameters", ":id=string", "@list_users", "@parameters", ":limit=int", ":offset=int"]
+        );
+
+        let config = Config {
+            distinguish_repeated_headings: true,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(
+            result,
+            vec![
+                "@get_user",
+                "@parameters",
+                ":id=string",
+                "@list_users",
+                "@parameters-2",
+                ":limit=int",
+                "@parameters-3",
+                ":offset=int",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heading_level_jumps() {
+        let heading = |level: usize, text: &str| IrNode::Heading {
+            level,
+            text: text.to_string(),
+            anchor: None,
+            line: 0,
+        };
+        // H1 -> H3 -> H2: the H2 closes the skipped-level H3 and nests under the H1
+        let ir = vec![
+            heading(1, "Guide"),
+            heading(3, "Deep"),
+            heading(2, "Mid"),
+            heading(3, "Leaf"),
+        ];
+        let config = Config {
+            scope_mode: ScopeMode::Concat,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@guide", "@guide_deep", "@guide_mid", "@guide_mid_leaf"]);
+
+        let config = Config {
+            scope_mode: ScopeMode::Concat,
+            normalize_heading_levels: true,
+            emit_scope_level: true,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@guide#1", "@guide_deep#3", "@guide_mid#2", "@guide_mid_leaf#3"]);
+
+        // Levels 2, 4, 5 become 1, 2, 3
+        let ir = vec![heading(2, "A"), heading(5, "B"), heading(4, "C"), heading(2, "D")];
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@a#1", "@a_b#3", "@a_c#2", "@d#1"]);
+    }
+
+    #[test]
+    fn test_root_scope_injection() {
+        let ir = vec![IrNode::Paragraph {
+            text: "orphan text".to_string(),
+            line: 0,
+        }];
+        let config = Config::default();
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result[0], "@root");
+        assert_eq!(result[1], "orphan text");
+    }
+
+    #[test]
+    fn test_kv_c0_separate() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Kv {
+                key: "Key A".to_string(),
+                value: "1".to_string(),
+                line: 0,
+            },
+            IrNode::Kv {
+                key: "Key B".to_string(),
+                value: "2".to_string(),
+                line: 0,
+            },
+        ];
+        let config = Config {
+            compression: 0,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert!(result.contains(&":key_a=1".to_string()));
+        assert!(result.contains(&":key_b=2".to_string()));
+    }
+
+    #[test]
+    fn test_normalize_kv_whitespace() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Kv {
+                key: "Name".to_string(),
+                value: "a  b\tc".to_string(),
+                line: 0,
+            },
+            IrNode::Kv {
+                key: "Cmd".to_string(),
+                value: "`ls  -la`".to_string(),
+                line: 0,
+            },
+        ];
+        let config = Config {
+            compression: 0,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@S", ":name=a b c", ":cmd=ls  -la"]);
+
+        let config = Config {
+            compression: 0,
+            normalize_kv_whitespace: false,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result[1], ":name=a  b\tc");
+    }
+
+    #[test]
+    fn test_max_kv_per_line_zero_does_not_panic() {
+        let kv = |key: &str| IrNode::Kv {
+            key: key.to_string(),
+            value: "1".to_string(),
+            line: 0,
+        };
+        let config = Config {
+            max_kv_per_line: 0,
+            ..Default::default()
+        };
+        // Both the plain and the prefix-extraction chunking paths
+        let result = emit_llmd(&[kv("a"), kv("b")], &[], "", &config);
+        assert_eq!(result, vec!["@root", ":a=1", ":b=1"]);
+        let ir = vec![kv("alpha_one"), kv("alpha_two"), kv("alpha_three")];
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@root", ":_pfx=alpha_", ":one=1", ":two=1", ":three=1"]);
+    }
+
+    #[test]
+    fn test_stable_kv_chunks_localize_inserts() {
+        let kv = |key: &str| IrNode::Kv {
+            key: key.to_string(),
+            value: "1".to_string(),
+            line: 0,
+        };
+        let keys = ["db_host", "db_port", "cache_ttl", "cache_size", "log_level", "log_file"];
+        let before: Vec<IrNode> = keys.iter().map(|k| kv(k)).collect();
+        let mut after = before.clone();
+        after.insert(2, kv("db_user"));
+        let config = Config {
+            prefix_extraction: false,
+            ..Default::default()
+        };
+        // Fixed-size chunks shift every later line
+        let result = emit_llmd(&before, &[], "", &config);
+        assert_eq!(result, vec!["@root", ":db_host=1 db_port=1 cache_ttl=1 cache_size=1", ":log_level=1 log_file=1"]);
+        let result = emit_llmd(&after, &[], "", &config);
+        assert_eq!(result, vec!["@root", ":db_host=1 db_port=1 db_user=1 cache_ttl=1", ":cache_size=1 log_level=1 log_file=1"]);
+
+        let config = Config {
+            stable_kv_chunks: true,
+            ..config
+        };
+        let result = emit_llmd(&before, &[], "", &config);
+        assert_eq!(result, vec!["@root", ":db_host=1 db_port=1", ":cache_ttl=1 cache_size=1", ":log_level=1 log_file=1"]);
+        let result = emit_llmd(&after, &[], "", &config);
+        assert_eq!(
+            result,
+            vec!["@root", ":db_host=1 db_port=1 db_user=1", ":cache_ttl=1 cache_size=1", ":log_level=1 log_file=1"]
+        );
+    }
+
+    #[test]
+    fn test_prefix_extraction_needs_nonempty_suffixes() {
+        let kv = |key: &str| IrNode::Kv {
+            key: key.to_string(),
+            value: "1".to_string(),
+            line: 0,
+        };
+        let config = Config {
+            min_prefix_len: 1,
+            ..Default::default()
+        };
+        let result = emit_llmd(&[kv("db_"), kv("db_host"), kv("db_port")], &[], "", &config);
+        assert_eq!(result, vec!["@root", ":db_=1 db_host=1 db_port=1"]);
+
+        let ir = vec![kv("net_a"), kv("net_host"), kv("net_port")];
+        assert_eq!(emit_llmd(&ir, &[], "", &config)[1], ":_pfx=net_");
+        let strict = Config {
+            prefix_min_suffix_len: 2,
+            ..config
+        };
+        assert_eq!(emit_llmd(&ir, &[], "", &strict), vec!["@root", ":net_a=1 net_host=1 net_port=1"]);
+    }
+
+    #[test]
+    fn test_prefix_extraction_never_emits_empty_key() {
+        let kv = |key: &str| IrNode::Kv {
+            key: key.to_string(),
+            value: "1".to_string(),
+            line: 0,
+        };
+        let ir = vec![kv("rate_limit"), kv("rate_limit-a"), kv("rate_limit-b")];
+        let expected: [&[&str]; 2] = [
+            &["@root", ":_pfx=rate_", ":limit=1 limit-a=1 limit-b=1"],
+            // "rate_" is shorter than min_prefix_len, so no prefix is extracted
+            &["@root", ":rate_limit=1 rate_limit-a=1 rate_limit-b=1"],
+        ];
+        for (min_prefix_len, lines) in [1, 6].into_iter().zip(expected) {
+            let config = Config {
+                min_prefix_len,
+                ..Default::default()
+            };
+            assert_eq!(emit_llmd(&ir, &[], "", &config), lines, "{}", min_prefix_len);
+        }
+    }
+
+    #[test]
+    fn test_uniform_bullets() {
+        let kv = |key: &str, value: &str| IrNode::Kv {
+            key: key.to_string(),
+            value: value.to_string(),
+            line: 0,
+        };
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "Server".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            kv("Host", "example.com"),
+            kv("Port", "8080"),
+            IrNode::Paragraph {
+                text: "Runs behind a proxy.".to_string(),
+                line: 0,
+            },
+        ];
+        let plain = emit_llmd(&ir, &[], "", &Config::default());
+        assert_eq!(plain, vec!["@server", ":host=example.com port=8080", "Runs behind a proxy."]);
+        let config = Config {
+            uniform_bullets: true,
+            ..Default::default()
+        };
+        let uniform = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(uniform, vec!["@server", "-host: example.com", "-port: 8080", "-Runs behind a proxy."]);
+    }
+
+    #[test]
+    fn test_uniform_bullet_lines_tables_and_prefixes() {
+        let lines: Vec<String> = [
+            "@s", ":_pfx=rate_", ":a=1 b=2", ":_cols=name¦type", ":id=int¦key", "x¦y", "::sh", "<<<", "k=v", ">>>", ".5 ratio",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+        assert_eq!(
+            uniform_bullet_lines(lines),
+            vec![
+                "@s", "-rate_a: 1", "-rate_b: 2", "-name | type", "-id: int | key", "-x | y", "::sh", "<<<", "k=v", ">>>",
+                "-\\.5 ratio",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compact_pipe_lists() {
+        let kv = |key: &str, value: &str| IrNode::Kv {
+            key: key.to_string(),
+            value: value.to_string(),
+            line: 0,
+        };
+        let ir = vec![
+            kv("fields", "id | **name** |  email"),
+            kv("expr", "a || b"),
+            kv("code", "`x | y`"),
+        ];
+        let config = Config {
+            compact_pipe_lists: true,
+            compression: 0,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@root", ":fields=id|name|email", ":expr=a || b", ":code=x | y"]);
+        let result = emit_llmd(&ir, &[], "", &Config { compression: 0, ..Default::default() });
+        assert_eq!(result[1], ":fields=id | name | email");
+    }
+
+    #[test]
+    fn test_merge_repeated_kv() {
+        let kv = |key: &str, value: &str| IrNode::Kv {
+            key: key.to_string(),
+            value: value.to_string(),
+            line: 0,
+        };
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            kv("Header", "A"),
+            kv("Method", "GET"),
+            kv("Header", "B"),
+            kv("Header", "C"),
+        ];
+        let config = Config {
+            merge_repeated_kv: true,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result[1], ":header=A;B;C method=GET");
+
+        let config = Config {
+            merge_repeated_kv: true,
+            kv_list_separator: "|".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(emit_llmd(&ir, &[], "", &config)[1], ":header=A|B|C method=GET");
+        assert_eq!(
+            emit_llmd(&ir, &[], "", &Config::default())[1],
+            ":header=A method=GET header=B header=C"
+        );
+    }
+
+    #[test]
+    fn test_kv_c1_merged() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Kv {
+                key: "A".to_string(),
+                value: "1".to_string(),
+                line: 0,
+            },
+            IrNode::Kv {
+                key: "B".to_string(),
+                value: "2".to_string(),
+                line: 0,
+            },
+        ];
+        let config = Config {
+            compression: 1,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert!(result.contains(&":a=1 b=2".to_string()));
+    }
+
+    #[test]
+    fn test_property_table() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Table {
+                rows: vec![
+                    vec!["Name".to_string(), "Value".to_string()],
+                    vec!["key1".to_string(), "val1".to_string()],
+                    vec!["key2".to_string(), "val2".to_string()],
+                ],
+                depth: 0,
+                line: 0,
+            },
+        ];
+        let config = Config::default();
+        let result = emit_llmd(&ir, &[], "", &config);
+        // "Value" is a generic header, should not emit :_col
+        assert!(result.contains(&":key1=val1 key2=val2".to_string()));
+    }
+
+    #[test]
+    fn test_bool_compress_exclude_cols() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Table {
+                rows: vec![
+                    vec!["Name".to_string(), "Enabled".to_string(), "Answer".to_string()],
+                    vec!["a".to_string(), "yes".to_string(), "yes".to_string()],
+                    vec!["b".to_string(), "no".to_string(), "no".to_string()],
+                ],
+                depth: 0,
+                line: 0,
+            },
+        ];
+        let config = Config {
+            bool_compress_exclude_cols: vec!["Answer".to_string()],
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert!(result.contains(&":a=Y¦yes b=N¦no".to_string()));
+    }
+
+    #[test]
+    fn test_collapse_single_row_tables() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Table {
+                rows: vec![
+                    vec!["Name".to_string(), "Type".to_string(), "Desc".to_string()],
+                    vec!["x".to_string(), "y".to_string(), "z".to_string()],
+                ],
+                depth: 0,
+                line: 0,
+            },
+        ];
+        let config = Config::default();
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@s", ":_cols=name¦type¦desc", ":x=y¦z"]);
+
+        let config = Config {
+            collapse_single_row_tables: true,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@s", ":name=x type=y desc=z"]);
+    }
+
+    #[test]
+    fn test_custom_bool_map() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Table {
+                rows: vec![
+                    vec!["Name".to_string(), "Power".to_string(), "Enabled".to_string()],
+                    vec!["a".to_string(), "On".to_string(), "yes".to_string()],
+                    vec!["b".to_string(), "off".to_string(), "no".to_string()],
+                ],
+                depth: 0,
+                line: 0,
+            },
+        ];
+        let mut config = Config::default();
+        config.bool_map.insert("on".to_string(), "Y".to_string());
+        config.bool_map.insert("OFF".to_string(), "N".to_string());
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert!(result.contains(&":a=Y¦yes b=N¦no".to_string()));
+
+        config.bool_map_extend = true;
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert!(result.contains(&":a=Y¦Y b=N¦N".to_string()));
+    }
+
+    #[test]
+    fn test_table_mode_csv() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Table {
+                rows: vec![
+                    vec!["City".to_string(), "Note".to_string()],
+                    vec!["Paris".to_string(), "big, old".to_string()],
+                    vec!["Oslo".to_string(), "say \"hei\"".to_string()],
+                ],
+                depth: 0,
+                line: 0,
+            },
+        ];
+        let config = Config {
+            table_mode: TableMode::Csv,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(
+            result,
+            vec![
+                "@s",
+                "::csv",
+                "<<<",
+                "City,Note",
+                "Paris,\"big, old\"",
+                "Oslo,\"say \"\"hei\"\"\"",
+                ">>>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_nested_in_list_item() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::ListItem {
+                depth: 0,
+                text: "Compare".to_string(),
+                style: ListStyle::Bullet,
+                marker: String::new(),
+                paragraphs: Vec::new(),
+                line: 0,
+            },
+            IrNode::Table {
+                rows: vec![
+                    vec!["Name".to_string(), "Speed".to_string()],
+                    vec!["a".to_string(), "fast".to_string()],
+                ],
+                depth: 1,
+                line: 0,
+            },
+        ];
+        let config = Config::default();
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@s", "-Compare", "-. Name¦Speed", "-. a¦fast"]);
+    }
+
+    #[test]
+    fn test_block_ref_emission() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::BlockRef { index: 0, line: 0 },
+        ];
+        let source = "```json\n{\"key\": \"value\"}\n```\n";
+        let blocks = vec![CodeBlock {
             index: 0,
             lang: "json".to_string(),
-            content: r#"{"key": "value"}"#.to_string(),
+            content: String::new(),
+            span: Some(8..24),
         }];
         let config = Config::default();
-        let result = emit_llmd(&ir, &blocks, &config);
+        let result = emit_llmd(&ir, &blocks, source, &config);
         assert!(result.contains(&"::json".to_string()));
         assert!(result.contains(&"<<<".to_string()));
+        assert!(result.contains(&r#"{"key": "value"}"#.to_string()));
         assert!(result.contains(&">>>".to_string()));
     }
 
+    #[test]
+    fn test_dedup_code_references_first_block() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::BlockRef { index: 0, line: 0 },
+            IrNode::BlockRef { index: 1, line: 0 },
+            IrNode::BlockRef { index: 2, line: 0 },
+            IrNode::BlockRef { index: 3, line: 0 },
+        ];
+        let block = |index: usize, content: &str| CodeBlock {
+            index,
+            lang: "py".to_string(),
+            content: content.to_string(),
+            span: None,
+        };
+        let blocks = vec![block(0, "import os"), block(1, "print(1)"), block(2, "print(1)"), block(3, "import os")];
+        let config = Config {
+            dedup_code: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            emit_llmd(&ir, &blocks, "", &config),
+            vec!["@s", "::py", "<<<", "import os", ">>>", "::py", "<<<", "print(1)", ">>>", "::py ref=1", "::py ref=0"]
+        );
+        assert_eq!(emit_llmd(&ir, &blocks, "", &Config::default()).len(), 17);
+
+        // CSV tables count as `<<<` blocks too
+        let table = IrNode::Table {
+            rows: vec![vec!["a".to_string(), "b".to_string()], vec!["1".to_string(), "2".to_string()]],
+            depth: 0,
+            line: 0,
+        };
+        let ir = vec![ir[0].clone(), table, ir[2].clone(), ir[3].clone()];
+        let config = Config {
+            dedup_code: true,
+            table_mode: TableMode::Csv,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &blocks, "", &config);
+        assert_eq!(result.last().unwrap(), "::py ref=1");
+        let sorted = Config {
+            sort_scopes: true,
+            ..config
+        };
+        assert!(!emit_llmd(&ir, &blocks, "", &sorted).iter().any(|l| l.contains("ref=")));
+    }
+
+    #[test]
+    fn test_compress_code_langs() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::BlockRef { index: 0, line: 0 },
+            IrNode::BlockRef { index: 1, line: 0 },
+        ];
+        let blocks = vec![
+            CodeBlock {
+                index: 0,
+                lang: "text".to_string(),
+                content: "  INFO   started\n\n  WARN    slow".to_string(),
+                span: None,
+            },
+            CodeBlock {
+                index: 1,
+                lang: "py".to_string(),
+                content: "if x:\n    y  = 1".to_string(),
+                span: None,
+            },
+        ];
+        let config = Config {
+            compress_code_langs: vec!["TEXT".to_string()],
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &blocks, "", &config);
+        assert!(result.contains(&"INFO started\nWARN slow".to_string()));
+        assert!(result.contains(&"if x:\n    y  = 1".to_string()));
+    }
+
     #[test]
     fn test_concat_scope_mode() {
         let ir = vec![
             IrNode::Heading {
                 level: 1,
                 text: "A".to_string(),
+                anchor: None,
+                line: 0,
             },
             IrNode::Heading {
                 level: 2,
                 text: "B".to_string(),
+                anchor: None,
+                line: 0,
             },
             IrNode::Paragraph {
                 text: "text".to_string(),
+                line: 0,
             },
         ];
-        let mut config = Config::default();
-        config.scope_mode = ScopeMode::Concat;
-        let result = emit_llmd(&ir, &[], &config);
+        let config = Config {
+            scope_mode: ScopeMode::Concat,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
         assert!(result.contains(&"@a_b".to_string()));
     }
 
+    #[test]
+    fn test_drop_scopes() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "Project".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Paragraph {
+                text: "intro".to_string(),
+                line: 0,
+            },
+            IrNode::Heading {
+                level: 2,
+                text: "Changelog".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Kv {
+                key: "Version".to_string(),
+                value: "1.0".to_string(),
+                line: 0,
+            },
+            IrNode::Heading {
+                level: 3,
+                text: "Older".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Paragraph {
+                text: "history".to_string(),
+                line: 0,
+            },
+            IrNode::Heading {
+                level: 2,
+                text: "Usage".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Paragraph {
+                text: "run it".to_string(),
+                line: 0,
+            },
+        ];
+        let config = Config {
+            drop_scopes: vec!["CHANGELOG".to_string()],
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@project", "intro", "@usage", "run it"]);
+    }
+
+    #[test]
+    fn test_hoist_summary() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Kv {
+                key: "A".to_string(),
+                value: "1".to_string(),
+                line: 0,
+            },
+            IrNode::Paragraph {
+                text: "Summary line".to_string(),
+                line: 0,
+            },
+            IrNode::Paragraph {
+                text: "Second paragraph".to_string(),
+                line: 0,
+            },
+            IrNode::Heading {
+                level: 1,
+                text: "T".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::Kv {
+                key: "B".to_string(),
+                value: "2".to_string(),
+                line: 0,
+            },
+        ];
+        let config = Config {
+            hoist_summary: true,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(
+            result,
+            vec!["@s", "Summary line", ":a=1", "Second paragraph", "@t", ":b=2"]
+        );
+    }
+
     #[test]
     fn test_list_depth_prefixes() {
         let ir = vec![
             IrNode::Heading {
                 level: 1,
                 text: "S".to_string(),
+                anchor: None,
+                line: 0,
             },
             IrNode::ListItem {
                 depth: 0,
                 text: "top".to_string(),
-                ordered: false,
+                style: ListStyle::Bullet,
+                marker: String::new(),
+                paragraphs: Vec::new(),
+                line: 0,
             },
             IrNode::ListItem {
                 depth: 1,
                 text: "nested".to_string(),
-                ordered: false,
+                style: ListStyle::Bullet,
+                marker: String::new(),
+                paragraphs: Vec::new(),
+                line: 0,
             },
         ];
         let config = Config::default();
-        let result = emit_llmd(&ir, &[], &config);
+        let result = emit_llmd(&ir, &[], "", &config);
         assert!(result.contains(&"-top".to_string()));
         assert!(result.contains(&"-. nested".to_string()));
     }
 
+    #[test]
+    fn test_leading_dot_never_reads_as_list_depth() {
+        let item = |depth: usize, text: &str| IrNode::ListItem {
+            depth,
+            text: text.to_string(),
+            style: ListStyle::Bullet,
+            marker: String::new(),
+            paragraphs: Vec::new(),
+            line: 0,
+        };
+        let ir = vec![
+            IrNode::Paragraph {
+                text: ". Note: dots lead this".to_string(),
+                line: 0,
+            },
+            item(0, ". Note: dots lead this"),
+            item(1, "Note: dots lead this"),
+            item(0, "+ plus"),
+            item(0, ".env files"),
+            item(1, ". dotted child"),
+        ];
+        let result = emit_llmd(&ir, &[], "", &Config::default());
+        assert_eq!(
+            result[1..],
+            [
+                ". Note: dots lead this",
+                "-\\. Note: dots lead this",
+                "-. Note: dots lead this",
+                "-\\+ plus",
+                "-\\.env files",
+                "-. . dotted child",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_item_continuation_paragraphs() {
+        let ir = vec![
+            IrNode::Heading {
+                level: 1,
+                text: "S".to_string(),
+                anchor: None,
+                line: 0,
+            },
+            IrNode::ListItem {
+                depth: 1,
+                text: "Install".to_string(),
+                style: ListStyle::Decimal,
+                marker: "1".to_string(),
+                paragraphs: vec!["Then verify.".to_string()],
+                line: 0,
+            },
+        ];
+        let result = emit_llmd(&ir, &[], "", &Config::default());
+        assert_eq!(result[1..], ["-. Install".to_string(), "-.+ Then verify.".to_string()]);
+    }
+
     #[test]
     fn test_find_common_prefix() {
         let keys = vec![
@@ -575,7 +2295,7 @@ mod tests {
             vec!["Name".into(), "Value".into()],
             vec!["key1".into(), "val1".into()],
         ];
-        assert_eq!(classify_table(&rows), "property");
+        assert_eq!(classify_table(&rows, false), "property");
     }
 
     #[test]
@@ -584,12 +2304,39 @@ mod tests {
             vec!["Name".into(), "Type".into(), "Desc".into()],
             vec!["key1".into(), "str".into(), "a desc".into()],
         ];
-        assert_eq!(classify_table(&rows), "keyed_multi");
+        assert_eq!(classify_table(&rows, false), "keyed_multi");
+    }
+
+    #[test]
+    fn test_classify_table_numeric_keys() {
+        let rows: Vec<Vec<String>> = vec![
+            vec!["ID".into(), "Name".into(), "Role".into()],
+            vec!["1".into(), "Ada".into(), "admin".into()],
+            vec!["2".into(), "Lin".into(), "viewer".into()],
+        ];
+        assert_eq!(classify_table(&rows, false), "raw");
+        assert_eq!(classify_table(&rows, true), "keyed_multi");
+
+        let mut dup = rows.clone();
+        dup[2][0] = "1".into();
+        assert_eq!(classify_table(&dup, true), "raw");
+
+        let ir = vec![IrNode::Table {
+            rows,
+            depth: 0,
+            line: 0,
+        }];
+        let config = Config {
+            allow_numeric_keys: true,
+            ..Default::default()
+        };
+        let result = emit_llmd(&ir, &[], "", &config);
+        assert_eq!(result, vec!["@root", ":_cols=id¦name¦role", ":1=Ada¦admin 2=Lin¦viewer"]);
     }
 
     #[test]
     fn test_classify_table_raw() {
         let rows = vec![vec!["Only col".into()]];
-        assert_eq!(classify_table(&rows), "raw");
+        assert_eq!(classify_table(&rows, false), "raw");
     }
 }
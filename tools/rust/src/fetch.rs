@@ -0,0 +1,23 @@
+use std::io::Read;
+use std::time::Duration;
+
+/// Fetch a document over HTTP(S), failing on anything but a 200 response.
+pub fn fetch_url(url: &str, timeout: Duration) -> Result<String, String> {
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+    let response = match agent.get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, response)) => {
+            return Err(format!("{} returned HTTP {} {}", url, code, response.status_text()));
+        }
+        Err(e) => return Err(format!("cannot fetch {}", e)),
+    };
+    if response.status() != 200 {
+        return Err(format!("{} returned HTTP {} {}", url, response.status(), response.status_text()));
+    }
+    let mut body = String::new();
+    response
+        .into_reader()
+        .read_to_string(&mut body)
+        .map_err(|e| format!("cannot read {}: {}", url, e))?;
+    Ok(body)
+}
@@ -1,3 +1,4 @@
+use crate::config::EmphasisMode;
 use fancy_regex::Regex as FancyRegex;
 use regex::Regex;
 use std::sync::LazyLock;
@@ -8,6 +9,8 @@ static RE_BOLD_UNDER: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"__(.+?)__").unwrap());
 static RE_ITALIC: LazyLock<FancyRegex> =
     LazyLock::new(|| FancyRegex::new(r"(?<!\*)\*(?!\*)(.+?)(?<!\*)\*(?!\*)").unwrap());
+static RE_BOLD_MARKED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new("\u{E000}(.*?)\u{E001}").unwrap());
 static RE_CODE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"`([^`]+)`").unwrap());
 static RE_STRIKE: LazyLock<Regex> =
@@ -17,6 +20,71 @@ static RE_IMG_LINK: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"!\[([^\]]*)\]\(([^)]+)\)").unwrap());
 static RE_LINK: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"\[([^\]]*)\]\(([^)]+)\)").unwrap());
+static RE_INTERNAL_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(^|[^!])\[([^\]]*)\]\(([^)\s:]*)#([^)\s]+)\)").unwrap());
+static RE_ENTITY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"&(#[0-9]{1,7}|#[xX][0-9a-fA-F]{1,6}|[A-Za-z][A-Za-z0-9]{1,31});").unwrap()
+});
+static RE_CODE_SPAN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"`[^`]+`").unwrap());
+static RE_HTML_SPECIAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<(kbd|sup|sub)>(.*?)</(?:kbd|sup|sub)>").unwrap());
+static RE_HTML_TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"</?[A-Za-z][A-Za-z0-9-]*(?:\s[^<>]*)?/?>").unwrap());
+static RE_ANGLE_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(https?://[^\s<>]+)>").unwrap());
+static RE_BARE_URL: LazyLock<FancyRegex> = LazyLock::new(|| {
+    FancyRegex::new(r#"(?<![\w<(\[])https?://[^\s<>()\[\]]*[^\s<>()\[\].,;:!?'"]"#).unwrap()
+});
+
+/// Private-use block that backslash-escaped ASCII punctuation is parked in
+/// while the emphasis and link passes run.
+const ESCAPE_BASE: u32 = 0xF700;
+
+/// Replace `\X` (X any ASCII punctuation) outside code spans with a placeholder
+/// that no inline pass treats as markup.
+fn protect_escapes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    let mut in_code = false;
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '`' => {
+                if in_code || text[i + 1..].contains('`') {
+                    in_code = !in_code;
+                }
+                out.push(c);
+            }
+            '\\' if !in_code => match chars.peek() {
+                Some(&(_, next)) if next.is_ascii_punctuation() => {
+                    out.push(char::from_u32(ESCAPE_BASE + next as u32).unwrap());
+                    chars.next();
+                }
+                _ => out.push(c),
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Replace every ASCII punctuation character with its [`protect_escapes`]
+/// placeholder, so no inline pass reads `text` as markup or a URL.
+fn park_punctuation(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_ascii_punctuation() { char::from_u32(ESCAPE_BASE + c as u32).unwrap() } else { c })
+        .collect()
+}
+
+/// Turn placeholders from [`protect_escapes`] back into the literal characters.
+fn restore_escapes(text: &str) -> String {
+    text.chars()
+        .map(|c| match (c as u32).checked_sub(ESCAPE_BASE) {
+            Some(code) if code < 0x80 => char::from_u32(code).unwrap(),
+            _ => c,
+        })
+        .collect()
+}
 
 pub fn strip_inline_markdown(text: &str) -> String {
     let text = RE_BOLD_STAR.replace_all(text, "$1").to_string();
@@ -26,6 +94,48 @@ pub fn strip_inline_markdown(text: &str) -> String {
     RE_STRIKE.replace_all(&text, "$1").to_string()
 }
 
+/// Wrap bold spans in private-use sentinels so they survive the other inline passes.
+fn mark_bold(text: &str) -> String {
+    let text = RE_BOLD_STAR.replace_all(text, "\u{E000}$1\u{E001}").to_string();
+    RE_BOLD_UNDER.replace_all(&text, "\u{E000}$1\u{E001}").to_string()
+}
+
+/// Turn sentinel-wrapped bold spans into `!word` markers, one per word.
+fn bold_markers(text: &str) -> String {
+    RE_BOLD_MARKED
+        .replace_all(text, |caps: &regex::Captures| {
+            caps[1]
+                .split_whitespace()
+                .map(|w| format!("!{}", w))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .to_string()
+}
+
+/// Strip links to their text, keeping the host of absolute URLs as
+/// `text<domain>`. Images and relative links keep only their text.
+pub fn process_links_with_domain(text: &str) -> String {
+    let text = RE_IMG_LINK.replace_all(text, "$1").to_string();
+    RE_LINK
+        .replace_all(&text, |caps: &regex::Captures| {
+            if caps[2].contains("://") {
+                format!("{}<{}>", &caps[1], url_domain(&caps[2]))
+            } else {
+                caps[1].to_string()
+            }
+        })
+        .to_string()
+}
+
+/// Turn relative links with a fragment (`[see](#setup)`, `[see](./other.md#setup)`)
+/// into `see<@setup>` scope references for stage 6 to resolve. The reference is
+/// marked with U+E002 after the `@` so a `<@name>` the author wrote is left alone.
+/// Links with a scheme and images are left alone.
+pub fn internal_link_refs(text: &str) -> String {
+    RE_INTERNAL_LINK.replace_all(text, "$1$2<@\u{E002}$4>").to_string()
+}
+
 pub fn process_links(text: &str, keep_urls: bool) -> String {
     if keep_urls {
         let text = RE_IMG_LINK.replace_all(text, "$1<$2>").to_string();
@@ -36,9 +146,210 @@ pub fn process_links(text: &str, keep_urls: bool) -> String {
     }
 }
 
+fn named_entity(name: &str) -> Option<&'static str> {
+    let decoded = match name {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        "nbsp" | "ensp" | "emsp" | "thinsp" => " ",
+        "mdash" => "\u{2014}",
+        "ndash" => "\u{2013}",
+        "hellip" => "...",
+        "lsquo" => "\u{2018}",
+        "rsquo" => "\u{2019}",
+        "ldquo" => "\u{201C}",
+        "rdquo" => "\u{201D}",
+        "laquo" => "\u{AB}",
+        "raquo" => "\u{BB}",
+        "bull" => "\u{2022}",
+        "middot" => "\u{B7}",
+        "copy" => "\u{A9}",
+        "reg" => "\u{AE}",
+        "trade" => "\u{2122}",
+        "deg" => "\u{B0}",
+        "plusmn" => "\u{B1}",
+        "times" => "\u{D7}",
+        "divide" => "\u{F7}",
+        "micro" => "\u{B5}",
+        "sect" => "\u{A7}",
+        "para" => "\u{B6}",
+        "euro" => "\u{20AC}",
+        "pound" => "\u{A3}",
+        "yen" => "\u{A5}",
+        "cent" => "\u{A2}",
+        "larr" => "\u{2190}",
+        "rarr" => "\u{2192}",
+        "uarr" => "\u{2191}",
+        "darr" => "\u{2193}",
+        "harr" => "\u{2194}",
+        "le" => "\u{2264}",
+        "ge" => "\u{2265}",
+        "ne" => "\u{2260}",
+        _ => return None,
+    };
+    Some(decoded)
+}
+
+fn decode_entity(entity: &str) -> Option<String> {
+    let code = if let Some(hex) = entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+        u32::from_str_radix(hex, 16).ok()?
+    } else if let Some(dec) = entity.strip_prefix('#') {
+        dec.parse().ok()?
+    } else {
+        return named_entity(entity).map(|s| s.to_string());
+    };
+    match code {
+        0 => None,
+        0xA0 => Some(" ".to_string()),
+        _ => char::from_u32(code).map(|c| c.to_string()),
+    }
+}
+
+/// Decode common named and numeric HTML entities outside inline code spans.
+/// Unknown entities are left as written; `&nbsp;` becomes a plain space.
+pub fn decode_html_entities(text: &str) -> String {
+    decode_entities_with(text, false)
+}
+
+/// Like [`decode_html_entities`], but decoded ASCII punctuation is parked in
+/// the [`protect_escapes`] placeholders, so `&#42;` stays a literal `*` through
+/// [`process_inline_with`] instead of starting emphasis.
+pub fn decode_html_entities_literal(text: &str) -> String {
+    decode_entities_with(text, true)
+}
+
+fn decode_entities_with(text: &str, literal: bool) -> String {
+    let decode = |segment: &str| {
+        RE_ENTITY
+            .replace_all(segment, |caps: &regex::Captures| match decode_entity(&caps[1]) {
+                Some(decoded) if literal => park_punctuation(&decoded),
+                Some(decoded) => decoded,
+                None => caps[0].to_string(),
+            })
+            .to_string()
+    };
+    let mut out = String::new();
+    let mut last = 0;
+    for m in RE_CODE_SPAN.find_iter(text) {
+        out.push_str(&decode(&text[last..m.start()]));
+        out.push_str(m.as_str());
+        last = m.end();
+    }
+    out.push_str(&decode(&text[last..]));
+    out
+}
+
+/// Render `<kbd>`, `<sup>` and `<sub>` contents as `[key]`, `^n` and `_n`.
+/// Multi-token sup/sub contents are parenthesized so `x<sup>n+1</sup>` reads `x^(n+1)`.
+fn html_special(tag: &str, inner: &str) -> String {
+    let wrap = |mark: char| {
+        if inner.chars().all(|c| c.is_alphanumeric() || c == '.') {
+            format!("{}{}", mark, inner)
+        } else {
+            format!("{}({})", mark, inner)
+        }
+    };
+    match tag.to_ascii_lowercase().as_str() {
+        "kbd" => format!("[{}]", inner),
+        "sup" => wrap('^'),
+        _ => wrap('_'),
+    }
+}
+
+/// Strip inline HTML tags outside code spans, keeping their text.
+///
+/// `<kbd>`, `<sup>` and `<sub>` keep their meaning (see [`html_special`]);
+/// autolinks like `<https://...>` are not tags and are left alone.
+pub fn strip_html(text: &str) -> String {
+    let strip = |segment: &str| {
+        let segment = RE_HTML_SPECIAL.replace_all(segment, |caps: &regex::Captures| {
+            html_special(&caps[1], caps[2].trim())
+        });
+        RE_HTML_TAG.replace_all(&segment, "").to_string()
+    };
+    let mut out = String::new();
+    let mut last = 0;
+    for m in RE_CODE_SPAN.find_iter(text) {
+        out.push_str(&strip(&text[last..m.start()]));
+        out.push_str(m.as_str());
+        last = m.end();
+    }
+    out.push_str(&strip(&text[last..]));
+    out
+}
+
+fn url_domain(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, r)| r);
+    rest.split(['/', '?', '#']).next().unwrap_or(rest)
+}
+
+/// Handle GFM autolinks (bare `https://...` and `<https://...>`).
+///
+/// When keeping URLs, bare URLs are wrapped as `<url>` so later passes treat
+/// them as a unit; otherwise autolinks are shortened to their domain.
+/// Trailing sentence punctuation is never considered part of a bare URL.
+pub fn process_bare_urls(text: &str, keep_urls: bool) -> String {
+    if keep_urls {
+        RE_BARE_URL.replace_all(text, "<$0>").to_string()
+    } else {
+        let text = RE_ANGLE_URL.replace_all(text, |caps: &regex::Captures| url_domain(&caps[1]).to_string());
+        RE_BARE_URL
+            .replace_all(&text, |caps: &fancy_regex::Captures| url_domain(&caps[0]).to_string())
+            .to_string()
+    }
+}
+
 pub fn process_inline(text: &str, compression: i32, keep_urls: bool) -> String {
-    let text = strip_inline_markdown(text);
-    process_links(&text, compression < 2 || keep_urls)
+    process_inline_with(text, compression, keep_urls, false, false, &EmphasisMode::Strip)
+}
+
+/// Like [`process_inline`], keeping bold as `!word` markers in [`EmphasisMode::Marker`].
+///
+/// Backslash escapes are honored: `\*not italic\*` keeps its asterisks and
+/// loses the backslashes. With `url_domains`, links whose URL is stripped keep
+/// its host as `text<domain>`. With `keep_images`, images always keep their
+/// path as `alt<path>`.
+pub fn process_inline_with(
+    text: &str,
+    compression: i32,
+    keep_urls: bool,
+    url_domains: bool,
+    keep_images: bool,
+    emphasis: &EmphasisMode,
+) -> String {
+    let text = protect_escapes(text);
+    let text = if keep_images {
+        RE_IMG_LINK
+            .replace_all(&text, |caps: &regex::Captures| {
+                format!("{}{}", &caps[1], park_punctuation(&format!("<{}>", &caps[2])))
+            })
+            .to_string()
+    } else {
+        text
+    };
+    let text = match emphasis {
+        EmphasisMode::Strip => strip_inline_markdown(&text),
+        EmphasisMode::Marker => strip_inline_markdown(&mark_bold(&text)),
+    };
+    let keep = compression < 2 || keep_urls;
+    let text = if !keep && url_domains {
+        process_links_with_domain(&text)
+    } else {
+        process_links(&text, keep)
+    };
+    // Bare URLs are only rewritten at c2, where later passes would otherwise mangle them
+    let text = if compression >= 2 {
+        process_bare_urls(&text, keep_urls)
+    } else {
+        text
+    };
+    let text = match emphasis {
+        EmphasisMode::Strip => text,
+        EmphasisMode::Marker => bold_markers(&text),
+    };
+    restore_escapes(&text)
 }
 
 #[cfg(test)]
@@ -56,6 +367,70 @@ mod tests {
         assert_eq!(strip_inline_markdown("*italic*"), "italic");
     }
 
+    #[test]
+    fn test_emphasis_strip_mode() {
+        let text = "**Never** share *private* keys";
+        assert_eq!(process_inline_with(text, 2, false, false, false, &EmphasisMode::Strip), "Never share private keys");
+    }
+
+    #[test]
+    fn test_emphasis_marker_mode() {
+        let marker = |t: &str| process_inline_with(t, 2, false, false, false, &EmphasisMode::Marker);
+        assert_eq!(marker("**Never** share *private* keys"), "!Never share private keys");
+        assert_eq!(marker("__the token__ expires"), "!the !token expires");
+        assert_eq!(marker("**[docs](https://example.com)**"), "!docs");
+        assert_eq!(marker("***both***"), "!both");
+    }
+
+    #[test]
+    fn test_strip_html_kbd() {
+        assert_eq!(strip_html("Press <kbd>Ctrl</kbd>+<kbd>C</kbd>"), "Press [Ctrl]+[C]");
+        assert_eq!(strip_html("<KBD>Esc</KBD> closes"), "[Esc] closes");
+    }
+
+    #[test]
+    fn test_strip_html_sup() {
+        assert_eq!(strip_html("x<sup>2</sup> + y<sup>2</sup>"), "x^2 + y^2");
+        assert_eq!(strip_html("2<sup>n+1</sup>"), "2^(n+1)");
+    }
+
+    #[test]
+    fn test_strip_html_sub() {
+        assert_eq!(strip_html("a<sub>n</sub> = a<sub>n-1</sub>"), "a_n = a_(n-1)");
+        assert_eq!(strip_html("H<sub>2</sub>O"), "H_2O");
+    }
+
+    #[test]
+    fn test_strip_html_generic_tags() {
+        assert_eq!(strip_html("<span class=\"x\">Heading</span>"), "Heading");
+        assert_eq!(strip_html("line<br/>break"), "linebreak");
+        assert_eq!(strip_html("see <https://example.com>"), "see <https://example.com>");
+        assert_eq!(strip_html("`<sup>2</sup>` and a < b > c"), "`<sup>2</sup>` and a < b > c");
+    }
+
+    #[test]
+    fn test_escaped_emphasis() {
+        assert_eq!(process_inline("\\*not italic\\*", 2, false), "*not italic*");
+        assert_eq!(process_inline("\\*\\*not bold\\*\\* but *this*", 2, false), "**not bold** but this");
+        assert_eq!(process_inline("\\_\\_init\\_\\_ and 2 \\* 3", 2, false), "__init__ and 2 * 3");
+    }
+
+    #[test]
+    fn test_internal_link_refs() {
+        assert_eq!(
+            internal_link_refs("[see](#setup), [api](./api.md#auth-flow) and [site](https://x.io/#top)"),
+            "see<@\u{E002}setup>, api<@\u{E002}auth-flow> and [site](https://x.io/#top)"
+        );
+        assert_eq!(internal_link_refs("![logo](#logo) [doc](./doc.md)"), "![logo](#logo) [doc](./doc.md)");
+    }
+
+    #[test]
+    fn test_escaped_link_and_literals() {
+        assert_eq!(process_inline("\\[not a link\\](x)", 2, false), "[not a link](x)");
+        assert_eq!(process_inline("C:\\dir \\# tag \\\\", 2, false), "C:\\dir # tag \\");
+        assert_eq!(process_inline("`\\#raw` \\*", 2, false), "\\#raw *");
+    }
+
     #[test]
     fn test_code_stripping() {
         assert_eq!(strip_inline_markdown("`code`"), "code");
@@ -109,4 +484,85 @@ mod tests {
             "bold link<url>"
         );
     }
+
+    #[test]
+    fn test_stripped_url_domain() {
+        let domain = |t: &str, c: i32| process_inline_with(t, c, false, true, false, &EmphasisMode::Strip);
+        assert_eq!(domain("see [here](https://github.com/x/y)", 2), "see here<github.com>");
+        assert_eq!(domain("[docs](docs/setup.md) and ![logo](https://cdn.example.com/l.png)", 2), "docs and logo");
+        assert_eq!(domain("[here](https://github.com/x/y)", 1), "here<https://github.com/x/y>");
+    }
+
+    #[test]
+    fn test_bare_url_keep_urls() {
+        assert_eq!(
+            process_bare_urls("Visit https://example.com/docs for docs", true),
+            "Visit <https://example.com/docs> for docs"
+        );
+        // Trailing sentence punctuation stays outside the URL
+        assert_eq!(
+            process_bare_urls("See https://example.com/a.", true),
+            "See <https://example.com/a>."
+        );
+    }
+
+    #[test]
+    fn test_bare_url_strip_to_domain() {
+        assert_eq!(
+            process_bare_urls("See https://example.com/a/b.", false),
+            "See example.com."
+        );
+        assert_eq!(
+            process_bare_urls("See <https://example.com/a>", false),
+            "See example.com"
+        );
+    }
+
+    #[test]
+    fn test_bare_url_inside_link_untouched() {
+        assert_eq!(
+            process_inline("[docs](https://example.com)", 0, false),
+            "docs<https://example.com>"
+        );
+        assert_eq!(process_inline("[docs](https://example.com)", 2, false), "docs");
+    }
+
+    #[test]
+    fn test_bare_url_only_rewritten_at_c2() {
+        let text = "See https://example.com/a. Or <https://example.com/b>";
+        assert_eq!(process_inline(text, 1, false), text);
+        assert_eq!(process_inline(text, 2, true), "See <https://example.com/a>. Or <https://example.com/b>");
+        assert_eq!(process_inline(text, 2, false), "See example.com. Or example.com");
+    }
+
+    #[test]
+    fn test_decode_named_entities() {
+        assert_eq!(decode_html_entities("R&amp;D &lt;tag&gt;"), "R&D <tag>");
+        assert_eq!(decode_html_entities("a&nbsp;b"), "a b");
+        assert_eq!(decode_html_entities("&unknown; stays"), "&unknown; stays");
+    }
+
+    #[test]
+    fn test_decode_numeric_entities() {
+        assert_eq!(decode_html_entities("a&#8212;b"), "a\u{2014}b");
+        assert_eq!(decode_html_entities("&#x41;&#X42;"), "AB");
+        assert_eq!(decode_html_entities("&#0;"), "&#0;");
+    }
+
+    #[test]
+    fn test_decoded_markup_stays_literal() {
+        let text = decode_html_entities_literal("Use &#42;literal&#42; and &#91;x&#93;(http://y)");
+        assert_eq!(
+            process_inline_with(&text, 2, false, false, false, &EmphasisMode::Strip),
+            "Use *literal* and [x](http://y)"
+        );
+    }
+
+    #[test]
+    fn test_decode_skips_code_spans() {
+        assert_eq!(
+            decode_html_entities("use `&amp;` for &amp;"),
+            "use `&amp;` for &"
+        );
+    }
 }
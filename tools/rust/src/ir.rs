@@ -1,22 +1,89 @@
+/// A parsed Markdown construct. `line` is the 1-based source line where it began.
 #[derive(Debug, Clone)]
 pub enum IrNode {
-    Heading { level: usize, text: String },
-    Paragraph { text: String },
-    ListItem { depth: usize, text: String, ordered: bool },
-    Table { rows: Vec<Vec<String>> },
-    Kv { key: String, value: String },
-    Blank,
-    BlockRef { index: usize },
+    Heading {
+        level: usize,
+        text: String,
+        /// Explicit id from a trailing `{#id}` or an `<a name="id">` in the heading
+        anchor: Option<String>,
+        line: usize,
+    },
+    Paragraph { text: String, line: usize },
+    ListItem {
+        depth: usize,
+        text: String,
+        style: ListStyle,
+        /// Ordinal as written (`3`, `b`, `iv`); empty for bullets
+        marker: String,
+        /// Further paragraphs indented under the item
+        paragraphs: Vec<String>,
+        line: usize,
+    },
+    Table { rows: Vec<Vec<String>>, depth: usize, line: usize },
+    Kv { key: String, value: String, line: usize },
+    /// Paragraph opening with a prose prefix such as `Note:`; `kind` is the marker name
+    Admonition { kind: String, text: String, line: usize },
+    Blank { line: usize },
+    BlockRef { index: usize, line: usize },
+}
+
+impl IrNode {
+    pub fn line(&self) -> usize {
+        match self {
+            IrNode::Heading { line, .. }
+            | IrNode::Paragraph { line, .. }
+            | IrNode::ListItem { line, .. }
+            | IrNode::Table { line, .. }
+            | IrNode::Kv { line, .. }
+            | IrNode::Admonition { line, .. }
+            | IrNode::Blank { line }
+            | IrNode::BlockRef { line, .. } => *line,
+        }
+    }
+}
+
+/// How a list item is numbered in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListStyle {
+    /// `-`, `*` or `+`
+    Bullet,
+    /// `1.`
+    Decimal,
+    /// `a.`
+    LowerAlpha,
+    /// `i.`, `iv.`
+    LowerRoman,
+}
+
+impl ListStyle {
+    pub fn is_ordered(self) -> bool {
+        self != ListStyle::Bullet
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct CodeBlock {
     pub index: usize,
     pub lang: String,
+    /// Block text; left empty when `span` points into the source instead
     pub content: String,
+    /// Byte range of the content in the normalized source, for [`crate::blocks::stage1_spans`]
+    pub span: Option<std::ops::Range<usize>>,
+}
+
+impl CodeBlock {
+    /// The block's content, read from `source` when it is stored as a span.
+    pub fn text<'a>(&'a self, source: &'a str) -> &'a str {
+        match &self.span {
+            Some(span) => &source[span.clone()],
+            None => &self.content,
+        }
+    }
 }
 
 pub struct Stage1Result {
     pub lines: Vec<String>,
     pub blocks: Vec<CodeBlock>,
+    /// 1-based source line for each entry in `lines`
+    pub source_lines: Vec<usize>,
 }
@@ -1,37 +1,166 @@
 pub mod blocks;
 pub mod compress;
 pub mod config;
+pub mod decompile;
+pub mod diag;
 pub mod emit;
+#[cfg(feature = "http")]
+pub mod fetch;
 pub mod inline;
 pub mod ir;
+pub mod merge;
 pub mod normalize;
 pub mod parse;
 pub mod postprocess;
+pub mod reader;
+pub mod refs;
+pub mod report;
 pub mod scope;
+pub mod scopemap;
+pub mod sink;
+pub mod subst;
+pub mod util;
 
 use config::Config;
+use diag::Diagnostic;
+use scopemap::{ScopeMapEntry, ScopeOrigin};
+use compress::C2Compressor;
+use sink::{OutputSink, StringSink};
+use std::collections::{BTreeMap, HashSet};
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
 
 pub fn compile(text: &str, config: &Config) -> String {
-    let compression = config.compression;
+    compile_with_scope_map(text, config).0
+}
+
+/// Compile after running `preprocess` once over the raw input, e.g. to expand
+/// macros. The hook runs before stage 0 and before code blocks are extracted, so
+/// fenced code is not protected from it.
+pub fn compile_with_hooks(text: &str, config: &Config, preprocess: Option<&dyn Fn(&str) -> String>) -> String {
+    match preprocess {
+        Some(hook) => compile(&hook(text), config),
+        None => compile(text, config),
+    }
+}
+
+/// Compile and report, for each emitted scope, its source heading and output line range.
+pub fn compile_with_scope_map(text: &str, config: &Config) -> (String, Vec<ScopeMapEntry>) {
+    let compiled = compile_detailed(text, config);
+    (compiled.output, compiled.scope_map)
+}
+
+/// An output line and the c2 edits that produced it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineExplanation {
+    /// 1-based output line
+    pub line: usize,
+    pub text: String,
+    pub edits: Vec<String>,
+}
+
+/// Compiled output plus the scope map, per-line compression log and warnings.
+pub struct Compilation {
+    pub output: String,
+    pub scope_map: Vec<ScopeMapEntry>,
+    pub explanations: Vec<LineExplanation>,
+    /// Parse and stage 6 validation warnings (already printed by `compile_detailed`)
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Compile, feeding the output to `sink` instead of building a string.
+pub fn compile_to_sink(text: &str, config: &Config, sink: &mut dyn OutputSink) {
+    let stages = run_stages(text, config, &C2Compressor::new(config));
+    report(&stages.diagnostics);
+    sink::drive(&stages.output, sink);
+}
+
+fn report(diagnostics: &[Diagnostic]) {
+    for d in diagnostics {
+        diag::warn(&d.message);
+    }
+}
+
+/// Stage 6 output lines, with what `compile_detailed` needs to explain them.
+struct Stages {
+    output: Vec<String>,
+    /// Index of the stage 5 line each output line came from (`None` for lines stage 6 added)
+    origins: Vec<Option<usize>>,
+    /// Index into `scopes` of the emitted scope each stage 5 line falls in
+    line_scopes: Vec<Option<usize>>,
+    /// c2 edits for each stage 5 line
+    edits: Vec<Vec<String>>,
+    scopes: Vec<ScopeOrigin>,
+    /// Parse and stage 6 validation warnings
+    diagnostics: Vec<Diagnostic>,
+}
 
-    // Stage 0
-    let lines = normalize::stage0(text);
+/// Stages 0 and 1: the normalized source and the lines and code blocks found in it.
+/// Blocks reference the source by byte range rather than copying it.
+fn run_stage1(text: &str, config: &Config) -> (String, ir::Stage1Result) {
+    let lines = normalize::stage0_with_tabs(text, config.tab_width);
+    let source = normalize::strip_comments(lines, &normalize::comment_regexes(config)).join("\n");
+    let mut stage1 = blocks::stage1_spans(&source);
+    if config.detect_indented_data {
+        blocks::extract_indented_data(&mut stage1);
+    }
+    if config.ascii_art_as_code {
+        blocks::extract_ascii_art(&mut stage1);
+    }
+    if config.promote_inline_code_lines {
+        blocks::promote_inline_code_lines(&mut stage1);
+    }
+    (source, stage1)
+}
+
+/// Code blocks of one language found by [`tally_code_langs`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CodeLangCount {
+    pub blocks: usize,
+    /// Total content bytes
+    pub bytes: usize,
+}
 
-    // Stage 1
+/// Add the code blocks in `text` to `tally`, keyed by lowercased language, with
+/// untagged blocks under `(none)`.
+pub fn tally_code_langs(text: &str, config: &Config, tally: &mut BTreeMap<String, CodeLangCount>) {
+    let (source, stage1) = run_stage1(text, config);
+    for block in &stage1.blocks {
+        let lang = match block.lang.trim() {
+            "" => "(none)".to_string(),
+            lang => lang.to_lowercase(),
+        };
+        let count = tally.entry(lang).or_default();
+        count.blocks += 1;
+        count.bytes += block.text(&source).len();
+    }
+}
+
+fn run_stages(text: &str, config: &Config, c2: &C2Compressor) -> Stages {
+    let compression = config.compression;
+
+    // Stages 0+1
+    let (source, stage1) = run_stage1(text, config);
     let ir::Stage1Result {
         lines: clean_lines,
         blocks,
-    } = blocks::stage1(&lines);
+        source_lines,
+    } = stage1;
+    let clean_lines = refs::resolve_references(&clean_lines);
 
     // Stage 2
-    let ir = parse::stage2(&clean_lines);
+    let (ir, parse_diagnostics) = parse::stage2_diagnosed(&clean_lines, &source_lines, config);
 
     // Stages 3+4
-    let mut output = emit::emit_llmd(&ir, &blocks, config);
+    let emit::Emitted {
+        lines: mut output,
+        scopes,
+    } = emit::emit_llmd_traced(&ir, &blocks, &source, config);
 
     // Stage 5
+    let mut edits: Vec<Vec<String>> = Vec::new();
     if compression >= 0 {
         output = compress::compress_c0(&output);
     }
@@ -39,19 +168,271 @@ pub fn compile(text: &str, config: &Config) -> String {
         output = compress::compress_c1(&output);
     }
     if compression >= 2 {
-        output = compress::compress_c2(&output, config);
+        (output, edits) = c2.compress_explained(&output);
     }
+    let line_scopes = scopemap::line_scopes(&output);
 
     // Stage 6
-    output = postprocess::stage6(&output, config);
+    let postprocess::Stage6 {
+        lines: output,
+        origins,
+        mut diagnostics,
+    } = postprocess::stage6_traced(&output, config);
+    diagnostics.splice(0..0, parse_diagnostics);
+    Stages {
+        output,
+        origins,
+        line_scopes,
+        edits,
+        scopes,
+        diagnostics,
+    }
+}
+
+pub fn compile_detailed(text: &str, config: &Config) -> Compilation {
+    let stages = run_stages(text, config, &C2Compressor::new(config));
+    report(&stages.diagnostics);
+    explain(stages)
+}
+
+fn render(output: &[String]) -> String {
+    let mut sink = StringSink::default();
+    sink::drive(output, &mut sink);
+    sink.output
+}
+
+/// One document compiled by [`compile_many`].
+#[derive(Debug, Clone)]
+pub struct CompileResult {
+    pub name: String,
+    pub output: String,
+    /// Approximate output tokens
+    pub tokens: usize,
+    /// Validation warnings for this document; returned rather than printed
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Compile a batch of named `(name, markdown)` documents, building the c2
+/// compressor once for all of them. Results are in input order; with the
+/// `rayon` feature the documents are compiled in parallel.
+pub fn compile_many(inputs: &[(String, String)], config: &Config) -> Vec<CompileResult> {
+    let c2 = C2Compressor::new(config);
+    let compile_one = |(name, text): &(String, String)| {
+        let stages = run_stages(text, config, &c2);
+        let output = render(&stages.output);
+        CompileResult {
+            name: name.clone(),
+            tokens: report::count_tokens(&output),
+            output,
+            diagnostics: stages.diagnostics,
+        }
+    };
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        inputs.par_iter().map(compile_one).collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        inputs.iter().map(compile_one).collect()
+    }
+}
+
+/// Build the output string, scope map and explanations from pipeline output.
+fn explain(stages: Stages) -> Compilation {
+    let Stages {
+        output,
+        origins,
+        line_scopes,
+        edits,
+        scopes,
+        diagnostics,
+    } = stages;
+
+    let mut explanations = Vec::with_capacity(output.len());
+    let mut line = 1;
+    for (text, origin) in output.iter().zip(&origins) {
+        explanations.push(LineExplanation {
+            line,
+            text: text.clone(),
+            edits: origin.and_then(|i| edits.get(i)).cloned().unwrap_or_default(),
+        });
+        line += text.split('\n').count();
+    }
+
+    let output_scopes: Vec<Option<usize>> = origins.iter().map(|o| o.and_then(|i| line_scopes[i])).collect();
+    let scope_map = scopemap::build_scope_map(&scopes, &output, &output_scopes);
+    let result = render(&output);
+    Compilation {
+        output: result,
+        scope_map,
+        explanations,
+        diagnostics,
+    }
+}
+
+/// Heuristic check for input that is already LLMD rather than Markdown.
+///
+/// Requires the first non-blank line to be an `@scope` and a meaningful share
+/// of lines (outside `<<<`/`>>>` blocks) to be LLMD structural lines: `@`
+/// scopes, `:` attributes and `::` blocks, `-` items and `~` markers.
+pub fn looks_like_llmd(text: &str) -> bool {
+    let mut lines = text.lines().map(|l| l.trim_end()).filter(|l| !l.is_empty());
+    match lines.next() {
+        Some(first) if first.starts_with('@') && !first.contains(' ') => {}
+        _ => return false,
+    }
+
+    let mut total = 1;
+    let mut structural = 1;
+    let mut in_block = false;
+    for line in lines {
+        if line == "<<<" || line == ">>>" {
+            in_block = line == "<<<";
+            total += 1;
+            structural += 1;
+            continue;
+        }
+        if in_block {
+            continue;
+        }
+        total += 1;
+        if line.starts_with(['@', ':', '-', '~']) {
+            structural += 1;
+        }
+    }
+    structural as f64 / total as f64 >= 0.15
+}
+
+/// Whether a [`ProgressEvent`] marks the start or the end of a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressStage {
+    Started,
+    Finished,
+}
+
+/// Per-file progress reported by [`compile_files_with_progress`].
+#[derive(Debug, Clone)]
+pub struct ProgressEvent {
+    pub stage: ProgressStage,
+    /// 0-based position of the file in the batch
+    pub index: usize,
+    pub total: usize,
+    pub path: PathBuf,
+    /// Approximate output tokens (0 when started)
+    pub tokens: usize,
+    /// Time spent on this file (zero when started)
+    pub elapsed: Duration,
+}
+
+/// Refuse an input of `len` bytes over `max_input_bytes` (0 = no limit).
+pub fn check_input_size(len: u64, config: &Config) -> Result<(), String> {
+    let max = config.max_input_bytes as u64;
+    if max > 0 && len > max {
+        return Err(format!("input is {} bytes, over max_input_bytes ({})", len, max));
+    }
+    Ok(())
+}
+
+/// Compile a file, refusing it without reading it when it is over `max_input_bytes`.
+pub fn compile_file(path: &Path, config: &Config) -> io::Result<String> {
+    check_input_size(std::fs::metadata(path)?.len(), config)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+    let text = std::fs::read_to_string(path)?;
+    Ok(compile(&text, config))
+}
 
-    let mut result = output.join("\n");
-    result.push('\n');
-    result
+/// Compile each file separately, reporting progress before and after each one.
+pub fn compile_files_with_progress(
+    files: &[PathBuf],
+    config: &Config,
+    mut on_progress: impl FnMut(ProgressEvent),
+) -> io::Result<Vec<String>> {
+    let total = files.len();
+    let mut outputs = Vec::with_capacity(total);
+    for (index, path) in files.iter().enumerate() {
+        let start = Instant::now();
+        on_progress(ProgressEvent {
+            stage: ProgressStage::Started,
+            index,
+            total,
+            path: path.clone(),
+            tokens: 0,
+            elapsed: Duration::ZERO,
+        });
+        let result = compile_file(path, config)?;
+        on_progress(ProgressEvent {
+            stage: ProgressStage::Finished,
+            index,
+            total,
+            path: path.clone(),
+            tokens: result.split_whitespace().count(),
+            elapsed: start.elapsed(),
+        });
+        outputs.push(result);
+    }
+    Ok(outputs)
+}
+
+/// Apply a document's leading `<!-- llmdc: {...} -->` override comment.
+///
+/// Returns the document without the comment and, when it had one, the merged
+/// and validated config to compile it with.
+pub fn document_config(text: &str, config: &Config) -> Result<(String, Option<Config>), String> {
+    let Some((json, rest)) = config::split_doc_overrides(text) else {
+        return Ok((text.to_string(), None));
+    };
+    let merged = config
+        .with_overrides(&json)
+        .map_err(|e| format!("invalid llmdc override comment: {}", e))?;
+    merged
+        .validate()
+        .map_err(|problems| format!("invalid llmdc override comment: {}", problems.join("; ")))?;
+    Ok((rest, Some(merged)))
 }
 
+/// Humanize a file stem into a heading title (`api_spec.md` -> `Api Spec`).
+pub fn title_from_filename(path: &Path) -> String {
+    let stem = path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+    stem.split(['_', '-', '.', ' '])
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            chars.next().map_or_else(String::new, |c| c.to_uppercase().chain(chars).collect())
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Prefix `text` with an H1 built from its filename unless it already starts with one.
+pub fn with_filename_title(text: &str, path: &Path) -> String {
+    let first = text.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    let title = title_from_filename(path);
+    if first.starts_with("# ") || title.is_empty() {
+        return text.to_string();
+    }
+    format!("# {}\n\n{}", title, text)
+}
+
+static RE_SOURCE_EXT: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?i)\.(md|markdown|llmd)$").unwrap());
+
+/// Whether a command-line input names an http(s) URL rather than a path.
+pub fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// Whether a URL's path (ignoring query and fragment) has a Markdown or LLMD extension.
+pub fn is_source_url(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    is_url(url) && RE_SOURCE_EXT.is_match(path)
+}
+
+/// Source files under `inputs` (files as given, directories recursively), sorted.
+/// A file reached more than once (say, listed and also inside a listed
+/// directory) appears once, under the first of its sorted paths.
 pub fn list_files(inputs: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
-    let re = regex::Regex::new(r"(?i)\.(md|markdown|llmd)$").unwrap();
+    let re = &*RE_SOURCE_EXT;
     let mut out: Vec<PathBuf> = Vec::new();
 
     for p in inputs {
@@ -80,6 +461,8 @@ pub fn list_files(inputs: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
     }
 
     out.sort();
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    out.retain(|p| seen.insert(std::fs::canonicalize(p).unwrap_or_else(|_| p.clone())));
     Ok(out)
 }
 
@@ -97,6 +480,18 @@ mod tests {
         assert!(result.contains("Some text"));
     }
 
+    #[test]
+    fn test_compile_with_hooks_preprocess() {
+        let input = "# Title\n\nSome text.\n\n```sh\nrun it\n```\n";
+        let config = Config::default();
+        let upper = |s: &str| s.to_uppercase();
+        let result = compile_with_hooks(input, &config, Some(&upper));
+        assert_eq!(result, compile(&input.to_uppercase(), &config));
+        assert!(result.contains("SOME TEXT"));
+        assert!(result.contains("RUN IT"));
+        assert_eq!(compile_with_hooks(input, &config, None), compile(input, &config));
+    }
+
     #[test]
     fn test_determinism() {
         let input = "# Title\n\nSome text.\n- item\n";
@@ -109,13 +504,461 @@ mod tests {
     #[test]
     fn test_compile_c0() {
         let input = "# Title\n\nKey: value\n";
-        let mut config = Config::default();
-        config.compression = 0;
+        let config = Config {
+            compression: 0,
+            ..Default::default()
+        };
         let result = compile(input, &config);
         assert!(result.contains("@Title"));
         assert!(result.contains(":key=value"));
     }
 
+    #[test]
+    fn test_looks_like_llmd() {
+        let llmd = "@title\n:key=value\ntext line\n@other\n-item\n";
+        assert!(looks_like_llmd(llmd));
+        let compiled = compile("# Title\n\nKey: value\n\n## Other\n\n- item\n", &Config::default());
+        assert!(looks_like_llmd(&compiled));
+        let prose = format!("@guide\n{}-one\n-two\n-three\n", "This is a sentence.\n".repeat(12));
+        assert!(looks_like_llmd(&prose));
+    }
+
+    #[test]
+    fn test_markdown_not_llmd() {
+        assert!(!looks_like_llmd("# Title\n\nKey: value\n"));
+        assert!(!looks_like_llmd("@mention in prose\nmore prose\n"));
+        assert!(!looks_like_llmd(""));
+    }
+
+    #[test]
+    fn test_keep_images() {
+        let input = "# T\n\nSee ![Arch] and ![logo](https://cdn.example.com/l.png) at [docs](https://example.com)\n\n[arch]: img/arch_v2.png\n";
+        assert_eq!(compile(input, &Config::default()), "@t\nSee Arch and logo at docs\n");
+        let config = Config {
+            keep_images: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            compile(input, &config),
+            "@t\nSee Arch<img/arch_v2.png> and logo<https://cdn.example.com/l.png> at docs\n"
+        );
+    }
+
+    #[test]
+    fn test_decoded_entities_stay_literal() {
+        let input = "# T\n\nUse &#42;literal&#42; and &#91;x&#93;(http://y)\n";
+        assert_eq!(compile(input, &Config::default()), "@t\nUse *literal* and [x](http://y)\n");
+    }
+
+    #[test]
+    fn test_bare_url_end_of_sentence_c2() {
+        let input = "# Title\n\nDocs live at https://example.com/guide.\n";
+        let kept = compile(
+            input,
+            &Config {
+                keep_urls: true,
+                ..Default::default()
+            },
+        );
+        assert!(kept.contains("Docs live at <https://example.com/guide>\n"));
+        let stripped = compile(input, &Config::default());
+        assert!(stripped.contains("Docs live at example.com\n"));
+    }
+
+    #[test]
+    fn test_reference_images_and_links() {
+        let input = "# Title\n\n![Flow][flow] per [the spec][spec] and ![Gone][missing]\n\n[flow]: flow.png\n[spec]: https://example.com/spec\n";
+        let result = compile(input, &Config::default());
+        assert_eq!(result, "@title\nFlow per the spec and Gone\n");
+        let kept = compile(
+            input,
+            &Config {
+                keep_urls: true,
+                ..Default::default()
+            },
+        );
+        assert!(kept.contains("Flow<flow.png> per the spec<https://example.com/spec> and Gone\n"));
+    }
+
+    #[test]
+    fn test_compile_detailed_explanations() {
+        let input = "# Title\n\nThe answer.\n\n```\na\nb\n```\nDone.\n";
+        let config = Config {
+            stopwords: vec!["the".to_string()],
+            anchor_every: 3,
+            ..Default::default()
+        };
+        let compiled = compile_detailed(input, &config);
+        assert_eq!(compiled.output, "@title\nanswer\n::code\n@title\n<<<\na b\n>>>\n@title\nDone\n");
+        let lines: Vec<(usize, &str)> = compiled
+            .explanations
+            .iter()
+            .map(|e| (e.line, e.text.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (1, "@title"),
+                (2, "answer"),
+                (3, "::code"),
+                (4, "@title"),
+                (5, "<<<"),
+                (6, "a b"),
+                (7, ">>>"),
+                (8, "@title"),
+                (9, "Done"),
+            ]
+        );
+        assert_eq!(compiled.explanations[1].edits, vec!["stopwords: The", "trailing period stripped"]);
+        assert!(compiled.explanations[3].edits.is_empty());
+        assert_eq!(compiled.explanations[8].edits, vec!["trailing period stripped"]);
+    }
+
+    #[test]
+    fn test_min_text_line_tokens_drops_compression_debris() {
+        let input = "# Notes\n\nThe cache is warm.\n\nAnd the.\n";
+        let config = Config {
+            stopwords: vec!["the".to_string(), "is".to_string()],
+            min_text_line_tokens: 2,
+            ..Default::default()
+        };
+        let result = compile(input, &config);
+        assert!(result.contains("cache warm"));
+        assert!(!result.contains("\nAnd"));
+        let kept = compile(input, &Config { min_text_line_tokens: 0, ..config });
+        assert!(kept.contains("\nAnd"));
+    }
+
+    #[test]
+    fn test_min_text_line_tokens_keeps_table_rows() {
+        let input = "# Notes\n\n| a | b | c |\n|---|---|---|\n| 1 | 2 | 3 |\n\n- a\n  - b\n- two words\n  - nested words here\n";
+        let config = Config {
+            min_text_line_tokens: 2,
+            ..Default::default()
+        };
+        assert_eq!(compile(input, &config), "@notes\n:_cols=a¦b¦c\n1¦2¦3\n-two words\n-. nested words here\n");
+    }
+
+    #[test]
+    fn test_explanations_follow_dropped_lines() {
+        let input = "# Notes\n\nAnd the.\n\nThe cache is warm.\n\nWait 5 seconds.\n";
+        let config = Config {
+            stopwords: vec!["the".to_string(), "is".to_string()],
+            min_text_line_tokens: 2,
+            ..Default::default()
+        };
+        let compiled = compile_detailed(input, &config);
+        let explained: Vec<(&str, Vec<String>)> = compiled
+            .explanations
+            .iter()
+            .map(|e| (e.text.as_str(), e.edits.clone()))
+            .collect();
+        assert_eq!(
+            explained,
+            vec![
+                ("@notes", vec![]),
+                ("cache warm", vec!["stopwords: The, is".to_string(), "trailing period stripped".to_string()]),
+                ("Wait 5 seconds", vec!["trailing period stripped".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_indented_json_minified() {
+        let input = "# Config\n\nExample:\n\n    {\n      \"port\": 8080,\n      \"tags\": [\"a b\"]\n    }\n";
+        let config = Config {
+            detect_indented_data: true,
+            code_transforms: [("json".to_string(), "minify".to_string())].into(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        let result = compile(input, &config);
+        assert!(result.contains("::json\n<<<\n{\"port\":8080,\"tags\":[\"a b\"]}\n>>>"), "{}", result);
+        assert!(!compile(input, &Config::default()).contains("::json"));
+
+        let bad = Config {
+            code_transforms: [("py".to_string(), "minify".to_string())].into(),
+            ..Default::default()
+        };
+        assert!(bad.validate().unwrap_err()[0].contains("code_transforms"));
+    }
+
+    #[test]
+    fn test_promote_inline_code_lines() {
+        let input = "# Build\n\nRun this:\n\n`echo done.`\n\nThen `make test` passes.\n";
+        assert_eq!(compile(input, &Config::default()), "@build\nRun this:\necho done\nThen make test passes\n");
+        let config = Config {
+            promote_inline_code_lines: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            compile(input, &config),
+            "@build\nRun this:\n::code\n<<<\necho done.\n>>>\nThen make test passes\n"
+        );
+    }
+
+    #[test]
+    fn test_tally_code_langs() {
+        let input = "# Code\n\n```js\nlet a = 1;\n```\n\n```python\nprint(1)\n```\n\n```JS\nf()\n```\n\n```\nplain\n```\n";
+        let mut tally = BTreeMap::new();
+        tally_code_langs(input, &Config::default(), &mut tally);
+        let counts: Vec<(&str, usize, usize)> = tally.iter().map(|(l, c)| (l.as_str(), c.blocks, c.bytes)).collect();
+        assert_eq!(counts, vec![("(none)", 1, 5), ("js", 2, 13), ("python", 1, 8)]);
+    }
+
+    #[test]
+    fn test_document_config_override_changes_compression() {
+        let body = "# Guide\n\nThe service is fast.\n\n| Key | Value |\n| --- | --- |\n| mode | fast |\n";
+        let input = format!("<!-- llmdc: {{\"compression\": 0}} -->\n{}", body);
+        let (text, doc) = document_config(&input, &Config::default()).unwrap();
+        let doc = doc.unwrap();
+        assert_eq!(doc.compression, 0);
+        let c0 = Config {
+            compression: 0,
+            ..Default::default()
+        };
+        let result = compile(&text, &doc);
+        assert_eq!(result, compile(body, &c0));
+        assert_ne!(result, compile(body, &Config::default()));
+        assert!(!result.contains("llmdc:"));
+
+        let (text, doc) = document_config(body, &Config::default()).unwrap();
+        assert_eq!(text, body);
+        assert!(doc.is_none());
+        assert!(document_config("<!-- llmdc: {\"compression\": 7} -->\n", &Config::default()).is_err());
+    }
+
+    #[test]
+    fn test_document_cannot_clear_forbidden_patterns() {
+        let config = Config {
+            forbidden_patterns: vec!["(?i)secret".to_string()],
+            ..Default::default()
+        };
+        let input = "<!-- llmdc: {\"forbidden_patterns\": []} -->\n# Notes\n\nThe secret plan.\n";
+        let err = document_config(input, &config).err().unwrap();
+        assert!(err.contains("'forbidden_patterns' cannot be overridden"), "{}", err);
+    }
+
+    #[test]
+    fn test_stripped_url_placeholder() {
+        let input = "# Links\n\nDetails [here](https://github.com/x/y).\n";
+        let config = Config {
+            stripped_url_placeholder: true,
+            ..Default::default()
+        };
+        assert!(compile(input, &config).contains("Details here<github.com>"));
+        assert!(compile(input, &Config::default()).contains("Details here\n"));
+    }
+
+    #[test]
+    fn test_mixed_marker_outline_keeps_letters() {
+        let input = "# Terms\n\n1. Scope\n   a. Services\n      i. Hosting\n   b. Term\n2. Fees\n";
+        let result = compile(input, &Config::default());
+        assert_eq!(result, "@terms\n-Scope\n-. a. Services\n-.. i. Hosting\n-. b. Term\n-Fees\n");
+    }
+
+    #[test]
+    fn test_custom_truncation_marker_survives_c2() {
+        let input = "# Guide\n\nThe first paragraph is kept.\n\nThe second paragraph is far too long for the budget.\n";
+        let config = Config {
+            max_tokens: 12,
+            truncation_marker: "~more in the full guide".to_string(),
+            ..Default::default()
+        };
+        let result = compile(input, &config);
+        assert_eq!(result, "@guide\nThe first paragraph is kept\n~more in the full guide\n");
+    }
+
+    #[test]
+    fn test_associate_table_captions() {
+        let input = "# Limits\n\n*Table 1: Rate limits*\n\n| Tier | RPS |\n|---|---|\n| free | 10 |\n| pro | 100 |\n\n*Second italic line stays text.*\n";
+        let config = Config {
+            associate_table_captions: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            compile(input, &config),
+            "@limits\n:_caption=Table 1: Rate limits\n:_col=rps\n:free=10 pro=100\nSecond italic line stays text\n"
+        );
+        let after = "# Limits\n\n| Tier | RPS |\n|---|---|\n| free | 10 |\n\n_Limits per tier_\n";
+        assert!(compile(after, &config).starts_with("@limits\n:_caption=Limits per tier\n"));
+        assert!(compile(input, &Config::default()).contains("\nTable 1: Rate limits\n"));
+    }
+
+    #[test]
+    fn test_preserve_anchors() {
+        let input = "## Old Title {#stable-id}\n\nBody.\n";
+        assert_eq!(compile(input, &Config::default()), "@old_title\nBody\n");
+        let config = Config {
+            preserve_anchors: true,
+            ..Default::default()
+        };
+        assert_eq!(compile(input, &config), "@stable-id\nBody\n");
+        assert_eq!(compile(&input.replace("Old Title", "New Name"), &config), "@stable-id\nBody\n");
+    }
+
+    #[test]
+    fn test_rewrite_internal_links() {
+        let input = "# Getting Started\n\nRun it.\n\n# Usage\n\nSee [setup](#getting-started) and [gone](./other.md#missing).\n\nPing <@missing> or <@usage>.\n\n```md\n[raw](#usage) <@usage>\n```\n";
+        assert_eq!(
+            compile(input, &Config::default()),
+            "@getting_started\nRun it\n@usage\nSee setup and gone\nPing <@missing> or <@usage>\n::md\n<<<\n[raw](#usage) <@usage>\n>>>\n"
+        );
+        let config = Config {
+            rewrite_internal_links: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            compile(input, &config),
+            "@getting_started\nRun it\n@usage\nSee setup<@getting_started> and gone\nPing <@missing> or <@usage>\n::md\n<<<\n[raw](#usage) <@usage>\n>>>\n"
+        );
+    }
+
+    #[test]
+    fn test_inline_short_lists() {
+        let config = Config {
+            inline_short_lists: 2,
+            ..Default::default()
+        };
+        let input = "# Mode\n\n- sync\n- async\n\nThen:\n\n- one\n- two\n- three\n\nAlso:\n\n- a\n  - nested\n- b\n\nLast:\n\n- short\n- a much longer item here\n\nPairs:\n\n- a; b\n- c\n";
+        assert_eq!(
+            compile(input, &config),
+            "@mode\n-sync; async\nThen:\n-one\n-two\n-three\nAlso:\n-a\n-. nested\n-b\nLast:\n-short\n-a much longer item here\nPairs:\n-a; b\n-c\n"
+        );
+    }
+
+    #[test]
+    fn test_kv_equals_option() {
+        let config = Config {
+            kv_equals: true,
+            ..Default::default()
+        };
+        assert_eq!(compile("# Client\n\ntimeout = 30\n", &config), "@client\n:timeout=30\n");
+    }
+
+    #[test]
+    fn test_empty_code_block_single_line() {
+        let result = compile("# Setup\n\n```js\n```\n\n```\n\n```\n\nDone.\n", &Config::default());
+        assert_eq!(result, "@setup\n::js (empty)\n::code (empty)\nDone\n");
+    }
+
+    #[test]
+    fn test_escaped_markers_are_literal() {
+        let result = compile("# Notes\n\n\\# not a heading\n\n\\*kept\\* *gone*\n", &Config::default());
+        assert_eq!(result, "@notes\n# not a heading\n*kept* gone\n");
+    }
+
+    #[test]
+    fn test_strip_html_option() {
+        let input = "# Keys\n\nPress <kbd>Ctrl</kbd>+<kbd>S</kbd> to save x<sup>2</sup> <span>now</span>\n";
+        let config = Config {
+            strip_html: true,
+            ..Default::default()
+        };
+        assert!(compile(input, &config).contains("[Ctrl]+[S]"));
+        assert!(compile(input, &config).contains("x^2"));
+        assert!(!compile(input, &config).contains("<span>"));
+        assert!(compile(input, &Config::default()).contains("<kbd>"));
+    }
+
+    #[test]
+    fn test_html_entities_decoded_at_c2() {
+        let input = "# Title\n\nTom&nbsp;&amp;&nbsp;Jerry &#8212; see `&lt;b&gt;`\n";
+        let result = compile(input, &Config::default());
+        assert!(result.contains("Tom & Jerry \u{2014} see &lt;b&gt;\n"));
+        let c0 = compile(
+            input,
+            &Config {
+                compression: 0,
+                ..Default::default()
+            },
+        );
+        assert!(c0.contains("Tom&nbsp;&amp;&nbsp;Jerry"));
+    }
+
+    #[test]
+    fn test_compile_many() {
+        let config = Config::default();
+        let inputs = vec![
+            ("a.md".to_string(), "# A\n\nFirst doc.\n".to_string()),
+            ("b.md".to_string(), "# B\n\n- one\n- two\n".to_string()),
+        ];
+        let results = compile_many(&inputs, &config);
+        assert_eq!(results.len(), 2);
+        for (result, (name, text)) in results.iter().zip(&inputs) {
+            assert_eq!(&result.name, name);
+            assert_eq!(result.output, compile(text, &config));
+            assert_eq!(result.tokens, result.output.split_whitespace().count());
+            assert!(result.diagnostics.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_compile_to_sink_matches_compile() {
+        let input = "# Setup\n\nInstall it.\n\n```sh\n@echo off\n```\n";
+        let mut sink = StringSink::default();
+        compile_to_sink(input, &Config::default(), &mut sink);
+        assert_eq!(sink.output, compile(input, &Config::default()));
+        let mut empty = StringSink::default();
+        compile_to_sink("", &Config::default(), &mut empty);
+        assert_eq!(empty.output, compile("", &Config::default()));
+    }
+
+    #[test]
+    fn test_compile_with_scope_map() {
+        let input = "Lead text.\n\n# API Reference\n\nIntro.\n\n## Auth\n\n- one\n- two\n";
+        let (output, map) = compile_with_scope_map(input, &Config::default());
+        assert_eq!(output, "@root\nLead text\n@api_reference\nIntro\n@auth\n-one\n-two\n");
+        let scopes: Vec<(&str, &str, [usize; 2], [usize; 2])> = map
+            .iter()
+            .map(|e| (e.scope.as_str(), e.heading.as_str(), e.source_lines, e.output_lines))
+            .collect();
+        assert_eq!(
+            scopes,
+            vec![
+                ("root", "", [1, 2], [1, 2]),
+                ("api_reference", "API Reference", [3, 6], [3, 4]),
+                ("auth", "Auth", [7, 10], [5, 7]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scope_map_follows_coalesced_scopes() {
+        let input = "# API\n\nOverview of the API.\n\nMore detail here.\n\n## get users\n\nmethod: GET\n\n## get orders\n\nLists orders.\n";
+        let config = Config {
+            coalesce_tiny_scopes: 1,
+            ..Default::default()
+        };
+        let (output, map) = compile_with_scope_map(input, &config);
+        assert_eq!(output, "@api\nOverview of the API\nMore detail here\n@get\n:get_users.method=GET\n-get_orders: Lists orders\n");
+        let scopes: Vec<(&str, &str, [usize; 2], [usize; 2])> = map
+            .iter()
+            .map(|e| (e.scope.as_str(), e.heading.as_str(), e.source_lines, e.output_lines))
+            .collect();
+        assert_eq!(scopes, vec![("api", "API", [1, 6], [1, 3]), ("get", "get users", [7, 13], [4, 6])]);
+    }
+
+    #[test]
+    fn test_with_filename_title() {
+        assert_eq!(title_from_filename(Path::new("docs/api_spec.md")), "Api Spec");
+        assert_eq!(title_from_filename(Path::new("getting-started.v2.md")), "Getting Started V2");
+        let path = Path::new("api_spec.md");
+        assert_eq!(with_filename_title("Some text\n", path), "# Api Spec\n\nSome text\n");
+        assert_eq!(with_filename_title("\n# Own Title\n", path), "\n# Own Title\n");
+        assert_eq!(with_filename_title("## Sub\n", path), "# Api Spec\n\n## Sub\n");
+    }
+
+    #[test]
+    fn test_is_source_url() {
+        assert!(is_url("https://example.com/README.md"));
+        assert!(!is_url("docs/README.md"));
+        assert!(is_source_url("https://example.com/docs/README.md?raw=1"));
+        assert!(is_source_url("http://example.com/a.markdown#top"));
+        assert!(!is_source_url("https://example.com/index.html"));
+        assert!(!is_source_url("docs/README.md"));
+    }
+
     #[test]
     fn test_compile_c2() {
         let input = "# Title\n\nKey: value\n";
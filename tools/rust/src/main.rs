@@ -1,20 +1,50 @@
 use clap::Parser;
 use llmdc::config::Config;
+use llmdc::diag::{self, Diagnostic, DiagnosticKind, Verbosity};
+use llmdc::report::{count_tokens, ReportEntry, RunReport};
+use llmdc::scopemap::ScopeMapEntry;
+use llmdc::CodeLangCount;
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 use std::process;
 
 #[derive(Parser)]
 #[command(name = "llmdc", about = "LLMD Compiler — compile Markdown to LLMD format")]
 struct Cli {
-    /// Input file(s) or directory
-    #[arg(required = true)]
+    /// Input file(s) or directory; `-` reads stdin
+    #[arg(required_unless_present_any = ["verify", "print_config"])]
     inputs: Vec<PathBuf>,
 
+    /// Name to report stdin input as in summaries, diagnostics and the report (default: stdin)
+    #[arg(long)]
+    stdin_name: Option<String>,
+
+    /// Check the `~sum` checksum footer of a compiled LLMD file and exit
+    #[arg(long, conflicts_with = "inputs")]
+    verify: Option<PathBuf>,
+
     /// Output file (default: stdout)
     #[arg(short, long)]
     output: Option<PathBuf>,
 
+    /// Write one output file per input into this directory
+    #[arg(long, conflicts_with = "output")]
+    out_dir: Option<PathBuf>,
+
+    /// File extension for outputs written with --out-dir
+    #[arg(long, default_value = "llmd", requires = "out_dir")]
+    output_extension: String,
+
+    /// Merge the compiled output into this LLMD file instead of writing it out
+    #[arg(long, conflicts_with_all = ["output", "out_dir"])]
+    append: Option<PathBuf>,
+
+    /// With --append, replace values of keys that already exist in the target
+    #[arg(long, requires = "append")]
+    overwrite: bool,
+
     /// Compression level (0-2, default: from config or 2)
     #[arg(short, long)]
     compression: Option<i32>,
@@ -35,9 +65,97 @@ struct Cli {
     #[arg(long)]
     anchor_every: Option<usize>,
 
+    /// Cut the output to about N whitespace-separated tokens, ending with the truncation marker
+    #[arg(long)]
+    max_tokens: Option<usize>,
+
+    /// Refuse any input larger than N bytes (default: 0 = no limit)
+    #[arg(long)]
+    max_input_bytes: Option<usize>,
+
+    /// Keep all code blocks verbatim, ignoring compress_code_langs from config
+    #[arg(long)]
+    no_compress_code_langs: bool,
+
+    /// Omit content-less parent scopes that have a single child
+    #[arg(long)]
+    flatten_single_child_scopes: bool,
+
+    /// Keep only these scopes (comma-separated) in the output
+    #[arg(long, value_delimiter = ',')]
+    only_scopes: Vec<String>,
+
+    /// Reorder scope regions alphabetically by scope name
+    #[arg(long)]
+    sort_scopes: bool,
+
+    /// Write a JSON map of each scope to its source heading and output lines
+    #[arg(long, conflicts_with = "out_dir")]
+    scope_map: Option<PathBuf>,
+
+    /// Write a JSON report of inputs, outputs, token counts and diagnostics
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Substitute `${VAR}` and `${VAR:-default}` from the environment before compiling
+    #[arg(long)]
+    env_subst: bool,
+
+    /// With --env-subst, fail on undefined variables instead of warning
+    #[arg(long, requires = "env_subst")]
+    env_strict: bool,
+
+    /// Timeout in seconds for http(s) inputs (requires the `http` feature)
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+
     /// Config file path
     #[arg(long)]
     config: Option<PathBuf>,
+
+    /// Print the effective config (defaults, config file and flags merged) as JSON and exit
+    #[arg(long)]
+    print_config: bool,
+
+    /// Output only each scope header and its first body line (or leading attribute lines), as a preview
+    #[arg(long)]
+    skeleton: bool,
+
+    /// Print the input files in compile order and exit without compiling
+    #[arg(long)]
+    list_files: bool,
+
+    /// Print each output line with the compression edits that produced it to stderr
+    #[arg(long)]
+    explain: bool,
+
+    /// Print a per-language tally of code blocks (count and bytes) across all inputs to stderr
+    #[arg(long)]
+    code_summary: bool,
+
+    /// Print debug diagnostics to stderr
+    #[arg(long)]
+    debug: bool,
+
+    /// Fail instead of warning on suspicious input
+    #[arg(long)]
+    strict: bool,
+
+    /// Exit with an error if any diagnostic of these kinds (comma-separated) was reported
+    #[arg(long, value_delimiter = ',')]
+    fail_on: Vec<String>,
+
+    /// Suppress the stderr summary and, unless --strict, warnings
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Report each input file on stderr and print the summary even for stdout output
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Recompile input that already looks like LLMD, or allow a Markdown output extension
+    #[arg(long)]
+    force: bool,
 }
 
 fn die(msg: &str) -> ! {
@@ -50,8 +168,319 @@ fn load_config(path: &PathBuf) -> Config {
     serde_json::from_str(&text).unwrap_or_else(|e| die(&format!("invalid config JSON: {}", e)))
 }
 
+/// Whether an input names stdin.
+fn is_stdin(fp: &Path) -> bool {
+    fp.as_os_str() == "-"
+}
+
+/// How an input is named in messages: its path, or `--stdin-name` for stdin.
+fn input_label(fp: &Path, cli: &Cli) -> String {
+    if is_stdin(fp) {
+        cli.stdin_name.clone().unwrap_or_else(|| "stdin".to_string())
+    } else {
+        fp.display().to_string()
+    }
+}
+
+/// Read an input file or stdin, or fetch it when it names an http(s) URL, dying
+/// when it is over `max_input_bytes`. Local files are checked before reading and
+/// stdin is read no further than the limit.
+fn read_input(fp: &Path, cli: &Cli, config: &Config) -> String {
+    let label = input_label(fp, cli);
+    let check = |len: u64| {
+        llmdc::check_input_size(len, config).unwrap_or_else(|e| die(&format!("{}: {}", label, e)));
+    };
+    if is_stdin(fp) {
+        let mut bytes = Vec::new();
+        let limit = match config.max_input_bytes {
+            0 => u64::MAX,
+            n => n as u64 + 1,
+        };
+        io::stdin()
+            .take(limit)
+            .read_to_end(&mut bytes)
+            .unwrap_or_else(|e| die(&format!("cannot read stdin: {}", e)));
+        // Check the size first: the cut may have split a multi-byte character
+        if (bytes.len() as u64) >= limit {
+            die(&format!("{}: input is over max_input_bytes ({})", label, config.max_input_bytes));
+        }
+        return String::from_utf8(bytes).unwrap_or_else(|e| die(&format!("cannot read stdin: {}", e)));
+    }
+    let name = fp.to_string_lossy();
+    if llmdc::is_url(&name) {
+        #[cfg(feature = "http")]
+        {
+            let timeout = std::time::Duration::from_secs(cli.timeout);
+            let text = llmdc::fetch::fetch_url(&name, timeout).unwrap_or_else(|e| die(&e));
+            check(text.len() as u64);
+            return text;
+        }
+        #[cfg(not(feature = "http"))]
+        {
+            let _ = cli.timeout;
+            die(&format!("cannot fetch {}: llmdc was built without the `http` feature", name));
+        }
+    }
+    if let Ok(meta) = fs::metadata(fp) {
+        check(meta.len());
+    }
+    fs::read_to_string(fp).unwrap_or_else(|e| die(&format!("cannot read {}: {}", fp.display(), e)))
+}
+
+/// Resolve `${VAR}` placeholders from the process environment when --env-subst is set.
+fn substitute_env(text: String, fp: &Path, cli: &Cli, diagnostics: &mut Vec<Diagnostic>) -> String {
+    if !cli.env_subst {
+        return text;
+    }
+    let (text, undefined) = llmdc::subst::substitute_vars(&text, |name| std::env::var(name).ok());
+    if !undefined.is_empty() {
+        let msg = format!("{}: undefined variable(s): {}", input_label(fp, cli), undefined.join(", "));
+        if cli.env_strict {
+            die(&msg);
+        }
+        let msg = format!("warning: {}", msg);
+        diag::warn(&msg);
+        diagnostics.push(Diagnostic::new(DiagnosticKind::UndefinedVar, msg));
+    }
+    text
+}
+
+/// Output of [`compile_files`] with what `--scope-map` and `--report` need.
+#[derive(Default)]
+struct Compiled {
+    output: String,
+    scope_map: Vec<ScopeMapEntry>,
+    /// Tokens across all inputs as read
+    raw_tokens: usize,
+    diagnostics: Vec<Diagnostic>,
+    /// Code blocks by language, under `--code-summary`
+    code_langs: BTreeMap<String, CodeLangCount>,
+}
+
+/// Append a compiled segment, shifting its scope map and explanations past the lines
+/// already in the output.
+fn push_compiled(out: &mut Compiled, text: &str, config: &Config, cli: &Cli) {
+    if cli.code_summary {
+        llmdc::tally_code_langs(text, config, &mut out.code_langs);
+    }
+    let compiled = llmdc::compile_detailed(text, config);
+    let offset = out.output.lines().count();
+    out.diagnostics.extend(compiled.diagnostics);
+    out.scope_map.extend(compiled.scope_map.into_iter().map(|mut e| {
+        e.output_lines = [e.output_lines[0] + offset, e.output_lines[1] + offset];
+        e
+    }));
+    if cli.explain {
+        for e in &compiled.explanations {
+            eprintln!("explain: {}: {}", e.line + offset, e.text);
+            for edit in &e.edits {
+                eprintln!("    {}", edit);
+            }
+        }
+    }
+    out.output.push_str(&compiled.output);
+}
+
+/// Print the `--code-summary` tally to stderr, one language per line.
+fn print_code_summary(tally: &BTreeMap<String, CodeLangCount>) {
+    eprintln!("code blocks by language:");
+    for (lang, count) in tally {
+        eprintln!("  {}: {} block(s), {} bytes", lang, count.blocks, count.bytes);
+    }
+}
+
+/// Compile files as one concatenated document, passing through any that are already LLMD.
+fn compile_files(files: &[PathBuf], cli: &Cli, config: &Config) -> Compiled {
+    let mut out = Compiled::default();
+    let mut all_text = String::new();
+    for fp in files {
+        let label = input_label(fp, cli);
+        diag::verbose(&format!("compiling {}", label));
+        let content = substitute_env(read_input(fp, cli, config), fp, cli, &mut out.diagnostics);
+        out.raw_tokens += count_tokens(&content);
+        let (mut content, doc_config) =
+            llmdc::document_config(&content, config).unwrap_or_else(|e| die(&format!("{}: {}", label, e)));
+        if !cli.force && llmdc::looks_like_llmd(&content) {
+            if cli.strict {
+                die(&format!("{} already looks like LLMD (use --force to recompile)", label));
+            }
+            let msg = format!(
+                "warning: {} already looks like LLMD; passing through unchanged (use --force to recompile)",
+                label
+            );
+            diag::warn(&msg);
+            out.diagnostics.push(Diagnostic::new(DiagnosticKind::LlmdInput, msg));
+            if !all_text.is_empty() {
+                push_compiled(&mut out, &all_text, config, cli);
+                all_text.clear();
+            }
+            out.output.push_str(content.trim_end());
+            out.output.push('\n');
+            continue;
+        }
+        let file_config = doc_config.as_ref().unwrap_or(config);
+        if file_config.title_from_filename {
+            content = llmdc::with_filename_title(&content, Path::new(&label));
+        }
+        if doc_config.is_some() {
+            // Compiled on its own so the override applies to this document only
+            if !all_text.is_empty() {
+                push_compiled(&mut out, &all_text, config, cli);
+                all_text.clear();
+            }
+            push_compiled(&mut out, &content, file_config, cli);
+            continue;
+        }
+        if !all_text.is_empty() {
+            all_text.push('\n');
+        }
+        all_text.push_str(&content);
+    }
+    if !all_text.is_empty() || out.output.is_empty() {
+        push_compiled(&mut out, &all_text, config, cli);
+    }
+    out
+}
+
+/// Describe one written output for `--report`.
+fn report_entry(files: &[PathBuf], output: &str, compiled: &Compiled, result: &str, cli: &Cli) -> ReportEntry {
+    ReportEntry {
+        inputs: files.iter().map(|f| input_label(f, cli)).collect(),
+        output: output.to_string(),
+        raw_tokens: compiled.raw_tokens,
+        compiled_tokens: count_tokens(result),
+        diagnostics: compiled.diagnostics.clone(),
+    }
+}
+
+fn write_report(path: &Path, config: &Config, entries: Vec<ReportEntry>) {
+    let report = RunReport::new(config.compression, entries);
+    let json = serde_json::to_string_pretty(&report).unwrap();
+    fs::write(path, json + "\n").unwrap_or_else(|e| die(&format!("cannot write {}: {}", path.display(), e)));
+}
+
+/// Under --strict, fail when the final output matches a `forbidden_patterns` entry.
+/// (Each match was already reported as a validation warning during stage 6.)
+fn check_forbidden(result: &str, config: &Config, cli: &Cli) {
+    if !cli.strict || config.forbidden_patterns.is_empty() {
+        return;
+    }
+    let found = llmdc::postprocess::find_forbidden(result, &config.forbidden_patterns);
+    if !found.is_empty() {
+        die(&format!("output matches forbidden patterns on {} line(s)", found.len()));
+    }
+}
+
+/// Keep only the `only_scopes` regions of compiled output, warning about names that matched nothing.
+fn apply_only_scopes(result: String, config: &Config, diagnostics: &mut Vec<Diagnostic>) -> String {
+    if config.only_scopes.is_empty() {
+        return result;
+    }
+    let (filtered, unknown) = llmdc::reader::filter_scopes(&result, &config.only_scopes);
+    for name in unknown {
+        let msg = format!("warning: only_scopes: no scope named '{}'", name);
+        diag::warn(&msg);
+        diagnostics.push(Diagnostic::new(DiagnosticKind::UnknownScope, msg));
+    }
+    filtered
+}
+
+/// Parse `--fail-on` kind names, refusing unknown ones.
+fn fail_on_kinds(cli: &Cli) -> Vec<DiagnosticKind> {
+    cli.fail_on
+        .iter()
+        .map(|name| {
+            DiagnosticKind::from_name(name.trim()).unwrap_or_else(|| {
+                let known: Vec<&str> = DiagnosticKind::ALL.iter().map(|k| k.name()).collect();
+                die(&format!("unknown diagnostic kind '{}' (expected one of: {})", name, known.join(", ")))
+            })
+        })
+        .collect()
+}
+
+/// Exit with an error when a reported diagnostic is of a `--fail-on` kind.
+fn check_fail_on<'a>(diagnostics: impl Iterator<Item = &'a Diagnostic>, fail_on: &[DiagnosticKind]) {
+    let diagnostics: Vec<Diagnostic> = diagnostics.cloned().collect();
+    if !diag::should_fail(&diagnostics, fail_on) {
+        return;
+    }
+    let failing: Vec<&Diagnostic> = diagnostics.iter().filter(|d| fail_on.contains(&d.kind)).collect();
+    die(&format!(
+        "{} diagnostic(s) matched --fail-on (first: {}: {})",
+        failing.len(),
+        failing[0].kind.name(),
+        failing[0].message
+    ));
+}
+
+/// Alphabetize scope regions when `sort_scopes` is set, refreshing any checksum footer.
+fn apply_sort_scopes(result: String, config: &Config) -> String {
+    if !config.sort_scopes {
+        return result;
+    }
+    let sorted = llmdc::reader::sort_scopes(llmdc::postprocess::strip_checksum(&result));
+    if config.emit_checksum {
+        llmdc::postprocess::append_checksum(&sorted)
+    } else {
+        sorted
+    }
+}
+
+/// Reduce compiled output to its `--skeleton` outline.
+fn apply_skeleton(result: String, config: &Config, cli: &Cli) -> String {
+    if !cli.skeleton {
+        return result;
+    }
+    let outline = llmdc::reader::skeleton(llmdc::postprocess::strip_checksum(&result));
+    if config.emit_checksum {
+        llmdc::postprocess::append_checksum(&outline)
+    } else {
+        outline
+    }
+}
+
+/// Derive `<out_dir>/<stem>.<ext>` for every input, refusing collisions.
+fn out_dir_targets(files: &[PathBuf], out_dir: &Path, ext: &str, cli: &Cli) -> Vec<PathBuf> {
+    let inputs: Vec<PathBuf> = files.iter().filter_map(|f| fs::canonicalize(f).ok()).collect();
+    let mut targets: Vec<PathBuf> = Vec::new();
+    for fp in files {
+        let label = PathBuf::from(input_label(fp, cli));
+        let stem = label.file_stem().map_or_else(|| "out".into(), |s| s.to_string_lossy());
+        let target = out_dir.join(format!("{}.{}", stem, ext));
+        if fs::canonicalize(&target).is_ok_and(|t| inputs.contains(&t)) {
+            die(&format!("output {} would overwrite an input file", target.display()));
+        }
+        if targets.contains(&target) {
+            die(&format!("multiple inputs would be written to {}", target.display()));
+        }
+        targets.push(target);
+    }
+    targets
+}
+
 fn main() {
     let cli = Cli::parse();
+    diag::set_debug(cli.debug);
+    diag::set_strict(cli.strict);
+    diag::set_verbosity(if cli.quiet {
+        Verbosity::Quiet
+    } else if cli.verbose {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    });
+
+    if let Some(ref path) = cli.verify {
+        let text =
+            fs::read_to_string(path).unwrap_or_else(|e| die(&format!("cannot read {}: {}", path.display(), e)));
+        match llmdc::postprocess::verify_checksum(&text) {
+            Ok(()) => {
+                diag::info(&format!("{}: checksum ok", path.display()));
+                return;
+            }
+            Err(e) => die(&format!("{}: {}", path.display(), e)),
+        }
+    }
 
     // Load config
     let mut config = if let Some(ref config_path) = cli.config {
@@ -90,38 +519,169 @@ fn main() {
     if let Some(n) = cli.anchor_every {
         config.anchor_every = n;
     }
+    if let Some(n) = cli.max_tokens {
+        config.max_tokens = n;
+    }
+    if let Some(n) = cli.max_input_bytes {
+        config.max_input_bytes = n;
+    }
+    if cli.flatten_single_child_scopes {
+        config.flatten_single_child_scopes = true;
+    }
+    if !cli.only_scopes.is_empty() {
+        config.only_scopes = cli.only_scopes.clone();
+    }
+    if cli.sort_scopes {
+        config.sort_scopes = true;
+    }
+    if cli.skeleton {
+        config.dedup_code = false;
+    }
+    if cli.no_compress_code_langs {
+        config.compress_code_langs.clear();
+    }
+
+    if cli.print_config {
+        println!("{}", serde_json::to_string_pretty(&config).unwrap());
+        return;
+    }
+    if let Err(problems) = config.validate() {
+        die(&format!("invalid config:\n  {}", problems.join("\n  ")));
+    }
+    let fail_on = fail_on_kinds(&cli);
 
     // Collect input files
-    let files = llmdc::list_files(&cli.inputs).unwrap_or_else(|e| die(&format!("{}", e)));
+    let (urls, paths): (Vec<PathBuf>, Vec<PathBuf>) = cli
+        .inputs
+        .iter()
+        .filter(|p| !is_stdin(p))
+        .cloned()
+        .partition(|p| llmdc::is_url(&p.to_string_lossy()));
+    let mut files = llmdc::list_files(&paths).unwrap_or_else(|e| die(&format!("{}", e)));
+    files.extend(urls.into_iter().filter(|u| llmdc::is_source_url(&u.to_string_lossy())));
+    if cli.inputs.iter().any(|p| is_stdin(p)) {
+        files.push(PathBuf::from("-"));
+    }
+    if cli.list_files {
+        for fp in &files {
+            println!("{}", input_label(fp, &cli));
+        }
+        return;
+    }
     if files.is_empty() {
         die("no input files found");
     }
 
-    // Compile all files
-    let mut all_text = String::new();
-    for fp in &files {
-        if !all_text.is_empty() {
-            all_text.push('\n');
+    if let Some(ref out_dir) = cli.out_dir {
+        let ext = cli.output_extension.trim_start_matches('.');
+        if ext.is_empty() {
+            die("output extension cannot be empty");
         }
-        let content =
-            fs::read_to_string(fp).unwrap_or_else(|e| die(&format!("cannot read {}: {}", fp.display(), e)));
-        all_text.push_str(&content);
+        if !cli.force && matches!(ext.to_lowercase().as_str(), "md" | "markdown") {
+            die(&format!("refusing output extension .{} (use --force to allow)", ext));
+        }
+        let targets = out_dir_targets(&files, out_dir, ext, &cli);
+        fs::create_dir_all(out_dir)
+            .unwrap_or_else(|e| die(&format!("cannot create {}: {}", out_dir.display(), e)));
+        let mut entries: Vec<ReportEntry> = Vec::new();
+        let mut code_langs: BTreeMap<String, CodeLangCount> = BTreeMap::new();
+        for (fp, target) in files.iter().zip(&targets) {
+            let inputs = std::slice::from_ref(fp);
+            let mut compiled = compile_files(inputs, &cli, &config);
+            for (lang, count) in std::mem::take(&mut compiled.code_langs) {
+                let total = code_langs.entry(lang).or_default();
+                total.blocks += count.blocks;
+                total.bytes += count.bytes;
+            }
+            let result = apply_only_scopes(std::mem::take(&mut compiled.output), &config, &mut compiled.diagnostics);
+            let result = apply_sort_scopes(result, &config);
+            let result = apply_skeleton(result, &config, &cli);
+            check_forbidden(&result, &config, &cli);
+            fs::write(target, &result)
+                .unwrap_or_else(|e| die(&format!("cannot write {}: {}", target.display(), e)));
+            let tokens = count_tokens(&result);
+            entries.push(report_entry(inputs, &target.display().to_string(), &compiled, &result, &cli));
+            diag::info(&format!(
+                "compiled {} -> {} (c{}, ~{} tokens)",
+                input_label(fp, &cli),
+                target.display(),
+                config.compression,
+                tokens
+            ));
+        }
+        if cli.code_summary {
+            print_code_summary(&code_langs);
+        }
+        if let Some(ref report_path) = cli.report {
+            write_report(report_path, &config, entries.clone());
+        }
+        check_fail_on(entries.iter().flat_map(|e| &e.diagnostics), &fail_on);
+        return;
+    }
+
+    if cli.scope_map.is_some() && !config.only_scopes.is_empty() {
+        die("--scope-map cannot be combined with only_scopes");
+    }
+    if cli.scope_map.is_some() && config.sort_scopes {
+        die("--scope-map cannot be combined with sort_scopes");
     }
+    if cli.scope_map.is_some() && cli.skeleton {
+        die("--scope-map cannot be combined with --skeleton");
+    }
+    let mut compiled = compile_files(&files, &cli, &config);
+    if cli.code_summary {
+        print_code_summary(&compiled.code_langs);
+    }
+    let result = apply_only_scopes(std::mem::take(&mut compiled.output), &config, &mut compiled.diagnostics);
+    let result = apply_sort_scopes(result, &config);
+    let result = apply_skeleton(result, &config, &cli);
+    check_forbidden(&result, &config, &cli);
 
-    let result = llmdc::compile(&all_text, &config);
+    if let Some(ref map_path) = cli.scope_map {
+        let json = serde_json::to_string_pretty(&compiled.scope_map).unwrap();
+        fs::write(map_path, json + "\n")
+            .unwrap_or_else(|e| die(&format!("cannot write {}: {}", map_path.display(), e)));
+    }
 
-    if let Some(ref output_path) = cli.output {
+    if let Some(ref target) = cli.append {
+        let existing = if target.exists() {
+            fs::read_to_string(target)
+                .unwrap_or_else(|e| die(&format!("cannot read {}: {}", target.display(), e)))
+        } else {
+            String::new()
+        };
+        let merged = llmdc::merge::merge_llmd(&existing, &result, cli.overwrite);
+        fs::write(target, &merged)
+            .unwrap_or_else(|e| die(&format!("cannot write {}: {}", target.display(), e)));
+        diag::info(&format!("appended {} file(s) -> {}", files.len(), target.display()));
+    } else if let Some(ref output_path) = cli.output {
         fs::write(output_path, &result)
             .unwrap_or_else(|e| die(&format!("cannot write {}: {}", output_path.display(), e)));
-        let tokens: usize = result.split_whitespace().filter(|t| !t.is_empty()).count();
-        eprintln!(
+        let tokens = count_tokens(&result);
+        diag::info(&format!(
             "compiled {} file(s) -> {} (c{}, ~{} tokens)",
             files.len(),
             output_path.display(),
             config.compression,
             tokens
-        );
+        ));
     } else {
         print!("{}", result);
+        let tokens = count_tokens(&result);
+        diag::verbose(&format!(
+            "compiled {} file(s) -> stdout (c{}, ~{} tokens)",
+            files.len(),
+            config.compression,
+            tokens
+        ));
+    }
+
+    if let Some(ref report_path) = cli.report {
+        let output = match (&cli.append, &cli.output) {
+            (Some(target), _) | (None, Some(target)) => target.display().to_string(),
+            (None, None) => "-".to_string(),
+        };
+        write_report(report_path, &config, vec![report_entry(&files, &output, &compiled, &result, &cli)]);
     }
+    check_fail_on(compiled.diagnostics.iter(), &fail_on);
 }
@@ -0,0 +1,85 @@
+use crate::reader::{format_kv_pairs, parse_kv_pairs, read_llmd, write_llmd, LlmdScope};
+
+/// Merge newly compiled LLMD into an existing document.
+///
+/// Scopes missing from `existing` are appended. For a scope present in both,
+/// new KV keys are added, existing keys keep their value unless `overwrite`
+/// is set, and other entries are added only if not already there, so merging
+/// the same input twice changes nothing.
+pub fn merge_llmd(existing: &str, new: &str, overwrite: bool) -> String {
+    let mut scopes = read_llmd(existing);
+    for incoming in read_llmd(new) {
+        match scopes.iter_mut().find(|s| s.name == incoming.name) {
+            Some(target) => merge_scope(target, incoming, overwrite),
+            None => scopes.push(incoming),
+        }
+    }
+    write_llmd(&scopes)
+}
+
+fn merge_scope(target: &mut LlmdScope, incoming: LlmdScope, overwrite: bool) {
+    for entry in incoming.body {
+        let Some(pairs) = parse_kv_pairs(&entry) else {
+            if !target.body.contains(&entry) {
+                target.body.push(entry);
+            }
+            continue;
+        };
+        let mut added: Vec<(String, String)> = Vec::new();
+        for (key, value) in pairs {
+            if !set_existing_key(target, &key, &value, overwrite) {
+                added.push((key, value));
+            }
+        }
+        if !added.is_empty() {
+            target.body.push(format_kv_pairs(&added));
+        }
+    }
+}
+
+/// Look for `key` in the scope's KV lines, replacing its value when `overwrite` is set.
+/// Returns whether the key was found.
+fn set_existing_key(target: &mut LlmdScope, key: &str, value: &str, overwrite: bool) -> bool {
+    for line in target.body.iter_mut() {
+        let Some(mut pairs) = parse_kv_pairs(line) else {
+            continue;
+        };
+        if let Some(pair) = pairs.iter_mut().find(|(k, _)| k == key) {
+            if overwrite && pair.1 != value {
+                pair.1 = value.to_string();
+                *line = format_kv_pairs(&pairs);
+            }
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXISTING: &str = "@auth\n:token=jwt ttl=1h\n-rotate keys\n@limits\n:rps=10\n";
+
+    #[test]
+    fn test_merge_adds_scopes_and_keys() {
+        let new = "@auth\n:ttl=2h scheme=bearer\n-rotate keys\n-use tls\n@billing\nmonthly\n";
+        let merged = merge_llmd(EXISTING, new, false);
+        assert_eq!(
+            merged,
+            "@auth\n:token=jwt ttl=1h\n-rotate keys\n:scheme=bearer\n-use tls\n@limits\n:rps=10\n@billing\nmonthly\n"
+        );
+        assert_eq!(merge_llmd(&merged, new, false), merged);
+    }
+
+    #[test]
+    fn test_merge_overwrite() {
+        let merged = merge_llmd(EXISTING, "@auth\n:ttl=2h\n", true);
+        assert_eq!(merged, "@auth\n:token=jwt ttl=2h\n-rotate keys\n@limits\n:rps=10\n");
+    }
+
+    #[test]
+    fn test_merge_into_empty() {
+        assert_eq!(merge_llmd("", "@a\nx\n", false), "@a\nx\n");
+    }
+}
@@ -1,13 +1,125 @@
+use crate::blocks::fence_open;
+use crate::config::Config;
+use crate::diag;
+use regex::Regex;
 use unicode_normalization::UnicodeNormalization;
 
 pub fn stage0(text: &str) -> Vec<String> {
+    stage0_with_tabs(text, 0)
+}
+
+/// Like [`stage0`], also expanding tabs outside code fences (see [`expand_tabs`]).
+/// A `tab_width` of 0 leaves tabs alone.
+pub fn stage0_with_tabs(text: &str, tab_width: usize) -> Vec<String> {
     let text: String = text.nfkc().collect();
     let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let mut fence: Option<String> = None;
     text.split('\n')
-        .map(|l| l.trim_end().to_string())
+        .map(|l| {
+            let in_fence = match &fence {
+                Some(f) => {
+                    if l.trim_end() == f {
+                        fence = None;
+                    }
+                    true
+                }
+                None => {
+                    fence = fence_open(l);
+                    fence.is_some()
+                }
+            };
+            if tab_width == 0 || in_fence || !l.contains('\t') {
+                l.trim_end().to_string()
+            } else {
+                expand_tabs(l, tab_width).trim_end().to_string()
+            }
+        })
+        .collect()
+}
+
+/// Replace each tab with spaces up to the next multiple of `tab_width` columns.
+pub fn expand_tabs(line: &str, tab_width: usize) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut col = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let n = tab_width - col % tab_width;
+            out.extend(std::iter::repeat_n(' ', n));
+            col += n;
+        } else {
+            out.push(c);
+            col += 1;
+        }
+    }
+    out
+}
+
+/// Compile `comment_patterns` when `strip_comments` is on, warning about and
+/// skipping invalid ones.
+pub fn comment_regexes(config: &Config) -> Vec<Regex> {
+    if !config.strip_comments {
+        return Vec::new();
+    }
+    config
+        .comment_patterns
+        .iter()
+        .filter_map(|p| match Regex::new(p) {
+            Ok(re) => Some(re),
+            Err(e) => {
+                diag::warn(&format!("warning: comment_patterns: invalid regex '{}': {}", p, e));
+                None
+            }
+        })
         .collect()
 }
 
+/// Remove author comments matching `patterns` everywhere except inside fenced
+/// code blocks. Patterns run over each run of lines between fences, so they can
+/// span lines; a match is replaced by the newlines it contained to keep line
+/// numbers stable.
+pub fn strip_comments(lines: Vec<String>, patterns: &[Regex]) -> Vec<String> {
+    if patterns.is_empty() {
+        return lines;
+    }
+    let flush = |chunk: &mut Vec<String>, out: &mut Vec<String>| {
+        if chunk.is_empty() {
+            return;
+        }
+        let mut text = chunk.join("\n");
+        for re in patterns {
+            text = re
+                .replace_all(&text, |caps: &regex::Captures| "\n".repeat(caps[0].matches('\n').count()))
+                .to_string();
+        }
+        out.extend(text.split('\n').map(|l| l.trim_end().to_string()));
+        chunk.clear();
+    };
+
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut chunk: Vec<String> = Vec::new();
+    let mut fence: Option<String> = None;
+    for line in lines {
+        match &fence {
+            Some(f) => {
+                if line.trim_end() == f {
+                    fence = None;
+                }
+                out.push(line);
+            }
+            None => match fence_open(&line) {
+                Some(f) => {
+                    flush(&mut chunk, &mut out);
+                    fence = Some(f);
+                    out.push(line);
+                }
+                None => chunk.push(line),
+            },
+        }
+    }
+    flush(&mut chunk, &mut out);
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -30,12 +142,66 @@ mod tests {
         assert_eq!(result, vec!["hello", "world"]);
     }
 
+    #[test]
+    fn test_expand_tabs_columns() {
+        assert_eq!(expand_tabs("\tx", 4), "    x");
+        assert_eq!(expand_tabs("  \tx", 4), "    x");
+        assert_eq!(expand_tabs("    \tx", 4), "        x");
+        assert_eq!(expand_tabs(" \t \tx", 4), "        x");
+        assert_eq!(expand_tabs("ab\tc\td", 4), "ab  c   d");
+        assert_eq!(expand_tabs("abcd\te", 4), "abcd    e");
+        assert_eq!(expand_tabs("\t\tx", 2), "    x");
+    }
+
+    #[test]
+    fn test_stage0_tabs_outside_fences() {
+        let text = "-\tone\n\t- two\n```make\nall:\n\tcc x.c\n```\n\tafter";
+        let result = stage0_with_tabs(text, 4);
+        assert_eq!(result, vec!["-   one", "    - two", "```make", "all:", "\tcc x.c", "```", "    after"]);
+        assert_eq!(stage0_with_tabs(text, 0), stage0(text));
+        assert_eq!(stage0(text)[1], "\t- two");
+    }
+
     #[test]
     fn test_empty_input() {
         let result = stage0("");
         assert_eq!(result, vec![""]);
     }
 
+    fn strip(text: &str) -> Vec<String> {
+        let config = Config {
+            strip_comments: true,
+            ..Default::default()
+        };
+        strip_comments(stage0(text), &comment_regexes(&config))
+    }
+
+    #[test]
+    fn test_comments_kept_by_default() {
+        assert!(comment_regexes(&Config::default()).is_empty());
+        let text = "a <!-- note --> b
+[//]: # (hidden)";
+        assert_eq!(strip_comments(stage0(text), &comment_regexes(&Config::default())), stage0(text));
+    }
+
+    #[test]
+    fn test_strip_html_comments() {
+        let result = strip("a <!-- internal: x --> b\n<!-- internal:\nspans lines -->\nc");
+        assert_eq!(result, vec!["a  b", "", "", "c"]);
+    }
+
+    #[test]
+    fn test_strip_link_label_comments() {
+        let result = strip("[//]: # (hidden note)\ntext\n[comment]: # (another)\n[docs]: https://example.com");
+        assert_eq!(result, vec!["", "text", "", "[docs]: https://example.com"]);
+    }
+
+    #[test]
+    fn test_comments_inside_fences_are_kept() {
+        let result = strip("```html\n<!-- markup -->\n```\n<!-- gone -->");
+        assert_eq!(result, vec!["```html", "<!-- markup -->", "```", ""]);
+    }
+
     #[test]
     fn test_nfkc_normalization() {
         // \u{FB01} (fi ligature) should be normalized to "fi"
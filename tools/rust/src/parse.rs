@@ -1,5 +1,8 @@
-use crate::ir::IrNode;
+use crate::config::Config;
+use crate::diag::{self, Diagnostic, DiagnosticKind};
+use crate::ir::{IrNode, ListStyle};
 use regex::Regex;
+use std::collections::HashMap;
 use std::sync::LazyLock;
 
 static RE_THEMATIC_BREAK: LazyLock<Regex> =
@@ -7,18 +10,84 @@ static RE_THEMATIC_BREAK: LazyLock<Regex> =
 
 static RE_HEADING: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^(#{1,6})\s+(.+)$").unwrap());
+static RE_HEADING_ID: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s*\{#([A-Za-z][\w-]*)\}$").unwrap());
+static RE_HEADING_HTML_ANCHOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<a\s+(?:name|id)="([^"]+)"\s*>\s*</a>\s*"#).unwrap());
 static RE_UL: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^(\s*)([-*+])\s+(.+)$").unwrap());
 static RE_OL: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^(\s*)(\d+)\.\s+(.+)$").unwrap());
+static RE_OL_LETTER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\s*)([a-z]|[ivxlcdm]+)\.\s+(.+)$").unwrap());
 static RE_BLOCK_REF: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^\u{27E6}BLOCK:(\d+)\u{27E7}$").unwrap());
 static RE_KV: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^([A-Za-z][A-Za-z0-9 _-]{0,63})\s*:\s+(.+)$").unwrap());
+    LazyLock::new(|| Regex::new(r"^([A-Za-z][A-Za-z0-9 _-]*)\s*:\s+(.+)$").unwrap());
+static RE_ADMONITION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([A-Za-z]+):\s+(.+)$").unwrap());
 static RE_TABLE_DELIM: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^\|?[\s:-]+\|").unwrap());
 
-fn is_structural(line: &str) -> bool {
+static RE_KV_EQUALS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([A-Za-z][A-Za-z0-9_-]*)\s*=\s*([^=\s].*)$").unwrap());
+static RE_EXPRESSION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[=<>!]=|\s[-+*/%]\s|;$").unwrap());
+
+/// Which lines count as KV: the longest key and whether `key = value` lines do too.
+#[derive(Clone, Copy)]
+struct KvRules {
+    max_key_len: usize,
+    equals: bool,
+}
+
+/// Match a `Key: value` line (or, with `kv_equals`, a `key = value` line) whose
+/// key fits within `max_key_len` characters.
+fn kv_captures(t: &str, kv: KvRules) -> Option<regex::Captures<'_>> {
+    if t.starts_with("http://") || t.starts_with("https://") {
+        return None;
+    }
+    RE_KV
+        .captures(t)
+        .or_else(|| if kv.equals { equals_captures(t) } else { None })
+        .filter(|caps| crate::util::grapheme_len(&caps[1]) <= kv.max_key_len)
+}
+
+/// Match `key = value` unless the line reads like an expression or statement
+/// (`==`, arithmetic, a trailing `;`).
+fn equals_captures(t: &str) -> Option<regex::Captures<'_>> {
+    if RE_EXPRESSION.is_match(t) {
+        return None;
+    }
+    RE_KV_EQUALS.captures(t)
+}
+
+/// Split a `Note: text` line into its marker name and body when the prefix is
+/// one of `keywords` (matched case-insensitively).
+fn admonition<'a>(t: &'a str, keywords: &HashMap<String, String>) -> Option<(String, &'a str)> {
+    let caps = RE_ADMONITION.captures(t)?;
+    let prefix = caps[1].to_lowercase();
+    let kind = keywords.iter().find(|(k, _)| k.to_lowercase() == prefix).map(|(_, v)| v.clone())?;
+    Some((kind, caps.get(2).unwrap().as_str()))
+}
+
+/// Collect the lines of a paragraph starting at `start`, stopping at a blank or
+/// structural line. Returns the trimmed lines and the index of the next line.
+fn paragraph_lines(lines: &[String], start: usize, kv: KvRules) -> (Vec<String>, usize) {
+    let mut para_lines = vec![lines[start].trim().to_string()];
+    let mut i = start + 1;
+    while i < lines.len() {
+        let nl = lines[i].trim();
+        if nl.is_empty() || is_structural(&lines[i], kv) {
+            break;
+        }
+        para_lines.push(nl.to_string());
+        i += 1;
+    }
+    (para_lines, i)
+}
+
+fn is_structural(line: &str, kv: KvRules) -> bool {
     let t = line.trim();
     if t.is_empty() {
         return true;
@@ -26,7 +95,7 @@ fn is_structural(line: &str) -> bool {
     if RE_HEADING.is_match(t) {
         return true;
     }
-    if RE_UL.is_match(t) || RE_OL.is_match(t) {
+    if RE_UL.is_match(t) || RE_OL.is_match(t) || RE_OL_LETTER.is_match(t) {
         return true;
     }
     if RE_BLOCK_REF.is_match(t) {
@@ -35,14 +104,40 @@ fn is_structural(line: &str) -> bool {
     if t.contains('|') {
         return true;
     }
-    if RE_KV.is_match(t) && !t.starts_with("http://") && !t.starts_with("https://") {
+    if kv_captures(t, kv).is_some() {
         return true;
     }
     false
 }
 
+/// Split an explicit anchor (`Title {#id}` or `<a name="id"></a>Title`) off heading text.
+fn heading_anchor(text: &str) -> (String, Option<String>) {
+    if let Some(caps) = RE_HEADING_ID.captures(text) {
+        let title = text[..caps.get(0).unwrap().start()].to_string();
+        return (title, Some(caps[1].to_string()));
+    }
+    if let Some(caps) = RE_HEADING_HTML_ANCHOR.captures(text) {
+        let title = RE_HEADING_HTML_ANCHOR.replace(text, "").trim().to_string();
+        return (title, Some(caps[1].to_string()));
+    }
+    (text.to_string(), None)
+}
+
+/// Split a table row on unescaped `|`; `\|` stays in the cell for inline processing.
 fn parse_table_row(row: &str) -> Vec<String> {
-    let mut cells: Vec<String> = row.split('|').map(|c| c.trim().to_string()).collect();
+    let mut cells: Vec<String> = Vec::new();
+    let mut cell = String::new();
+    let mut escaped = false;
+    for c in row.chars() {
+        if c == '|' && !escaped {
+            cells.push(cell.trim().to_string());
+            cell.clear();
+        } else {
+            cell.push(c);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    cells.push(cell.trim().to_string());
     if !cells.is_empty() && cells[0].is_empty() {
         cells.remove(0);
     }
@@ -52,21 +147,121 @@ fn parse_table_row(row: &str) -> Vec<String> {
     cells
 }
 
+/// Collect a list item's indented continuation, starting just after the item line.
+///
+/// Lines directly under the item extend its text; text after a blank line that
+/// is indented at least to `content_indent` becomes an extra paragraph. Stops at
+/// anything structural, including nested list items. Returns the extra text,
+/// the extra paragraphs and the index of the first unconsumed line.
+fn list_continuation(
+    lines: &[String],
+    start: usize,
+    content_indent: usize,
+    kv: KvRules,
+) -> (Vec<String>, Vec<String>, usize) {
+    let mut extra: Vec<String> = Vec::new();
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut i = start;
+    loop {
+        let mut k = i;
+        while k < lines.len() && lines[k].trim().is_empty() {
+            k += 1;
+        }
+        if k >= lines.len() {
+            break;
+        }
+        let indent = lines[k].len() - lines[k].trim_start().len();
+        if indent < content_indent || is_structural(&lines[k], kv) {
+            break;
+        }
+        let after_blank = k > i;
+        let mut para: Vec<String> = Vec::new();
+        while k < lines.len() && !lines[k].trim().is_empty() && !is_structural(&lines[k], kv) {
+            para.push(lines[k].trim().to_string());
+            k += 1;
+        }
+        if after_blank || !paragraphs.is_empty() {
+            paragraphs.push(para.join(" "));
+        } else {
+            extra.extend(para);
+        }
+        i = k;
+    }
+    (extra, paragraphs, i)
+}
+
+/// Tell lettered from roman numbering for a `RE_OL_LETTER` marker, given the
+/// style of the previous item at the same indentation. A lone `i` starts a roman
+/// list unless it continues a lettered one (`h.`, `i.`).
+fn letter_style(marker: &str, prev: Option<ListStyle>) -> ListStyle {
+    let roman = marker.chars().all(|c| "ivxlcdm".contains(c));
+    match prev {
+        Some(ListStyle::LowerAlpha) if marker.len() == 1 => ListStyle::LowerAlpha,
+        Some(ListStyle::LowerRoman) if roman => ListStyle::LowerRoman,
+        _ if roman && (marker.len() > 1 || marker == "i") => ListStyle::LowerRoman,
+        _ => ListStyle::LowerAlpha,
+    }
+}
+
+/// Whether the next non-blank line after `i` is a lettered or roman list item,
+/// so a lone prose line like "i. think so" is not taken for a list.
+fn next_is_letter_item(lines: &[String], i: usize) -> bool {
+    lines[i + 1..]
+        .iter()
+        .find(|l| !l.trim().is_empty())
+        .is_some_and(|l| RE_OL_LETTER.is_match(l))
+}
+
 pub fn stage2(lines: &[String]) -> Vec<IrNode> {
+    let source_lines: Vec<usize> = (1..=lines.len()).collect();
+    stage2_with_lines(lines, &source_lines, &Config::default())
+}
+
+/// Parse stage 1 output, tagging each node with its entry in `source_lines`.
+pub fn stage2_with_lines(lines: &[String], source_lines: &[usize], config: &Config) -> Vec<IrNode> {
+    let (ir, diagnostics) = stage2_diagnosed(lines, source_lines, config);
+    for d in &diagnostics {
+        diag::warn(&d.message);
+    }
+    ir
+}
+
+/// Like [`stage2_with_lines`], returning parse warnings instead of printing them.
+pub fn stage2_diagnosed(lines: &[String], source_lines: &[usize], config: &Config) -> (Vec<IrNode>, Vec<Diagnostic>) {
+    let max_depth = config.max_nesting_depth;
+    // Items nested past `max_depth`, and the source line of the first
+    let mut flattened = 0;
+    let mut first_flattened = 0;
+    let kv = KvRules {
+        max_key_len: config.max_kv_key_len,
+        equals: config.kv_equals,
+    };
     let mut ir: Vec<IrNode> = Vec::new();
     let mut i = 0;
     let n = lines.len();
+    // Depth of the most recent list item, while still inside that list
+    let mut list_depth: Option<usize> = None;
+    // Distinct indentation widths of the enclosing list items, outermost first,
+    // with the numbering style last seen at each
+    let mut list_indents: Vec<(usize, ListStyle)> = Vec::new();
 
     while i < n {
         let line = &lines[i];
         let t = line.trim();
+        let line_no = source_lines[i];
 
         if t.is_empty() {
-            ir.push(IrNode::Blank);
+            ir.push(IrNode::Blank { line: line_no });
             i += 1;
             continue;
         }
 
+        let indent = line.len() - line.trim_start().len();
+        if indent == 0 && !RE_UL.is_match(line) && !RE_OL.is_match(line) && !RE_OL_LETTER.is_match(line) {
+            list_depth = None;
+            list_indents.clear();
+        }
+
         // Skip thematic breaks (---, ***, ___)
         if RE_THEMATIC_BREAK.is_match(t) {
             i += 1;
@@ -75,15 +270,23 @@ pub fn stage2(lines: &[String]) -> Vec<IrNode> {
 
         if let Some(caps) = RE_BLOCK_REF.captures(t) {
             let index: usize = caps[1].parse().unwrap();
-            ir.push(IrNode::BlockRef { index });
+            ir.push(IrNode::BlockRef {
+                index,
+                line: line_no,
+            });
             i += 1;
             continue;
         }
 
         if let Some(caps) = RE_HEADING.captures(t) {
             let level = caps[1].len();
-            let text = caps[2].trim().to_string();
-            ir.push(IrNode::Heading { level, text });
+            let (text, anchor) = heading_anchor(caps[2].trim());
+            ir.push(IrNode::Heading {
+                level,
+                text,
+                anchor,
+                line: line_no,
+            });
             i += 1;
             continue;
         }
@@ -98,61 +301,130 @@ pub fn stage2(lines: &[String]) -> Vec<IrNode> {
                     rows.push(parse_table_row(lines[i].trim()));
                     i += 1;
                 }
-                ir.push(IrNode::Table { rows });
+                // An indented table directly under a list item nests inside it
+                let depth = match list_depth {
+                    Some(d) if indent > 0 => (d + 1).min(max_depth),
+                    _ => 0,
+                };
+                ir.push(IrNode::Table {
+                    rows,
+                    depth,
+                    line: line_no,
+                });
                 continue;
             }
         }
 
-        if let Some(caps) = RE_UL.captures(line) {
-            let depth = caps[1].len() / 2;
-            let text = caps[3].trim().to_string();
+        let list_caps = RE_UL
+            .captures(line)
+            .map(|c| (c, ListStyle::Bullet))
+            .or_else(|| RE_OL.captures(line).map(|c| (c, ListStyle::Decimal)))
+            .or_else(|| {
+                RE_OL_LETTER
+                    .captures(line)
+                    .filter(|_| list_depth.is_some() || next_is_letter_item(lines, i))
+                    .map(|c| (c, ListStyle::LowerAlpha))
+            });
+        if let Some((caps, style)) = list_caps {
+            let width = caps[1].len();
+            while list_indents.last().is_some_and(|&(w, _)| w > width) {
+                list_indents.pop();
+            }
+            let prev = list_indents.last().filter(|&&(w, _)| w == width).map(|&(_, s)| s);
+            let style = match style {
+                ListStyle::LowerAlpha => letter_style(&caps[2], prev),
+                _ => style,
+            };
+            if prev.is_some() {
+                list_indents.pop();
+            }
+            list_indents.push((width, style));
+            let mut depth = list_indents.len() - 1;
+            if depth > max_depth {
+                if flattened == 0 {
+                    first_flattened = line_no;
+                }
+                flattened += 1;
+                depth = max_depth;
+            }
+            let marker = if style.is_ordered() { caps[2].to_string() } else { String::new() };
+            let content_indent = caps.get(3).unwrap().start();
+            let (extra, paragraphs, next) = list_continuation(lines, i + 1, content_indent, kv);
+            let mut text = caps[3].trim().to_string();
+            for e in extra {
+                text.push(' ');
+                text.push_str(&e);
+            }
+            list_depth = Some(depth);
             ir.push(IrNode::ListItem {
                 depth,
                 text,
-                ordered: false,
+                style,
+                marker,
+                paragraphs,
+                line: line_no,
             });
-            i += 1;
+            i = next;
             continue;
         }
 
-        if let Some(caps) = RE_OL.captures(line) {
-            let depth = caps[1].len() / 2;
-            let text = caps[3].trim().to_string();
-            ir.push(IrNode::ListItem {
-                depth,
-                text,
-                ordered: true,
+        if config.prose_admonitions {
+            if let Some((kind, body)) = admonition(t, &config.admonition_keywords) {
+                let (mut para_lines, next) = paragraph_lines(lines, i, kv);
+                para_lines[0] = body.to_string();
+                ir.push(IrNode::Admonition {
+                    kind,
+                    text: para_lines.join(" "),
+                    line: line_no,
+                });
+                i = next;
+                continue;
+            }
+        }
+
+        if let Some(caps) = kv_captures(t, kv) {
+            let key = caps[1].to_string();
+            let value = caps[2].trim().to_string();
+            ir.push(IrNode::Kv {
+                key,
+                value,
+                line: line_no,
             });
             i += 1;
             continue;
         }
-
-        if let Some(caps) = RE_KV.captures(t) {
-            if !t.starts_with("http://") && !t.starts_with("https://") {
-                let key = caps[1].to_string();
-                let value = caps[2].trim().to_string();
-                ir.push(IrNode::Kv { key, value });
-                i += 1;
-                continue;
+        if diag::debug_enabled() {
+            if let Some(caps) = RE_KV.captures(t) {
+                diag::debug(
+                    line_no,
+                    &format!(
+                        "key `{}` is longer than max_kv_key_len ({}), treating line as text",
+                        caps[1].trim(),
+                        kv.max_key_len
+                    ),
+                );
             }
         }
 
         // Paragraph: merge consecutive non-structural lines
-        let mut para_lines = vec![t.to_string()];
-        i += 1;
-        while i < n {
-            let nl = lines[i].trim();
-            if nl.is_empty() || is_structural(&lines[i]) {
-                break;
-            }
-            para_lines.push(nl.to_string());
-            i += 1;
-        }
+        let (para_lines, next) = paragraph_lines(lines, i, kv);
+        i = next;
         ir.push(IrNode::Paragraph {
             text: para_lines.join(" "),
+            line: line_no,
         });
     }
-    ir
+    let mut diagnostics = Vec::new();
+    if flattened > 0 {
+        diagnostics.push(Diagnostic::new(
+            DiagnosticKind::DeepNesting,
+            format!(
+                "warning: line {}: {} list item(s) nested deeper than max_nesting_depth ({}) were flattened",
+                first_flattened, flattened, max_depth
+            ),
+        ));
+    }
+    (ir, diagnostics)
 }
 
 #[cfg(test)]
@@ -167,7 +439,7 @@ mod tests {
     fn test_heading() {
         let ir = stage2(&s(&["# Title"]));
         match &ir[0] {
-            IrNode::Heading { level, text } => {
+            IrNode::Heading { level, text, .. } => {
                 assert_eq!(*level, 1);
                 assert_eq!(text, "Title");
             }
@@ -175,11 +447,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_escaped_heading_marker() {
+        let ir = stage2(&s(&["\\# not a heading", "", "\\- not a list"]));
+        match (&ir[0], &ir[2]) {
+            (IrNode::Paragraph { text: a, .. }, IrNode::Paragraph { text: b, .. }) => {
+                assert_eq!(a, "\\# not a heading");
+                assert_eq!(b, "\\- not a list");
+            }
+            _ => panic!("expected paragraphs"),
+        }
+    }
+
+    #[test]
+    fn test_heading_anchors() {
+        let ir = stage2(&s(&["## Old Title {#stable-id}", "# <a name=\"intro\"></a>Intro", "# Sets {a, b}"]));
+        let headings: Vec<(&str, Option<&str>)> = ir
+            .iter()
+            .map(|n| match n {
+                IrNode::Heading { text, anchor, .. } => (text.as_str(), anchor.as_deref()),
+                other => panic!("expected heading, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            headings,
+            vec![("Old Title", Some("stable-id")), ("Intro", Some("intro")), ("Sets {a, b}", None)]
+        );
+    }
+
     #[test]
     fn test_paragraph_merging() {
         let ir = stage2(&s(&["line one", "line two", "", "line three"]));
         match &ir[0] {
-            IrNode::Paragraph { text } => assert_eq!(text, "line one line two"),
+            IrNode::Paragraph { text, .. } => assert_eq!(text, "line one line two"),
             _ => panic!("expected paragraph"),
         }
     }
@@ -191,11 +491,12 @@ mod tests {
             IrNode::ListItem {
                 depth,
                 text,
-                ordered,
+                style,
+                ..
             } => {
                 assert_eq!(*depth, 0);
                 assert_eq!(text, "item one");
-                assert!(!ordered);
+                assert!(!style.is_ordered());
             }
             _ => panic!("expected list item"),
         }
@@ -205,20 +506,123 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_multi_paragraph_list_item() {
+        let ir = stage2(&s(&[
+            "1. Install the tool",
+            "   from the release page.",
+            "",
+            "   Then verify the checksum.",
+            "",
+            "   - nested note",
+            "2. Run it",
+        ]));
+        match &ir[0] {
+            IrNode::ListItem { text, paragraphs, .. } => {
+                assert_eq!(text, "Install the tool from the release page.");
+                assert_eq!(paragraphs, &vec!["Then verify the checksum.".to_string()]);
+            }
+            other => panic!("expected list item, got {:?}", other),
+        }
+        assert!(matches!(ir[1], IrNode::Blank { line: 5 }));
+        assert!(matches!(&ir[2], IrNode::ListItem { depth: 1, .. }));
+        match &ir[3] {
+            IrNode::ListItem { text, paragraphs, line, .. } => {
+                assert_eq!(text, "Run it");
+                assert!(paragraphs.is_empty());
+                assert_eq!(*line, 7);
+            }
+            other => panic!("expected list item, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_irregular_list_indentation() {
+        let ir = stage2(&s(&[
+            "- a",
+            "   - b",
+            "       - c",
+            "   - d",
+            "    - e",
+            "- f",
+            "  - g",
+        ]));
+        let depths: Vec<usize> = ir
+            .iter()
+            .map(|n| match n {
+                IrNode::ListItem { depth, .. } => *depth,
+                other => panic!("expected list item, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(depths, vec![0, 1, 2, 1, 2, 0, 1]);
+    }
+
     #[test]
     fn test_ordered_list() {
         let ir = stage2(&s(&["1. first", "2. second"]));
         match &ir[0] {
-            IrNode::ListItem { ordered, .. } => assert!(ordered),
+            IrNode::ListItem { style, .. } => assert_eq!(*style, ListStyle::Decimal),
             _ => panic!("expected ordered list item"),
         }
     }
 
+    fn list_items(ir: &[IrNode]) -> Vec<(usize, ListStyle, &str, &str)> {
+        ir.iter()
+            .filter_map(|n| match n {
+                IrNode::ListItem {
+                    depth,
+                    style,
+                    marker,
+                    text,
+                    ..
+                } => Some((*depth, *style, marker.as_str(), text.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_mixed_marker_outline() {
+        let ir = stage2(&s(&[
+            "1. Scope",
+            "   a. Services",
+            "      i. Hosting",
+            "      ii. Support",
+            "   b. Term",
+            "2. Fees",
+        ]));
+        assert_eq!(
+            list_items(&ir),
+            vec![
+                (0, ListStyle::Decimal, "1", "Scope"),
+                (1, ListStyle::LowerAlpha, "a", "Services"),
+                (2, ListStyle::LowerRoman, "i", "Hosting"),
+                (2, ListStyle::LowerRoman, "ii", "Support"),
+                (1, ListStyle::LowerAlpha, "b", "Term"),
+                (0, ListStyle::Decimal, "2", "Fees"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lettered_list_continues_past_i() {
+        let ir = stage2(&s(&["h. eighth", "i. ninth", "j. tenth"]));
+        let styles: Vec<ListStyle> = list_items(&ir).iter().map(|item| item.1).collect();
+        assert_eq!(styles, vec![ListStyle::LowerAlpha; 3]);
+    }
+
+    #[test]
+    fn test_lone_letter_line_is_prose() {
+        let ir = stage2(&s(&["i. think this is prose", "", "Next paragraph"]));
+        assert!(matches!(&ir[0], IrNode::Paragraph { text, .. } if text == "i. think this is prose"));
+        assert!(list_items(&ir).is_empty());
+    }
+
     #[test]
     fn test_kv_pair() {
         let ir = stage2(&s(&["Key: value"]));
         match &ir[0] {
-            IrNode::Kv { key, value } => {
+            IrNode::Kv { key, value, .. } => {
                 assert_eq!(key, "Key");
                 assert_eq!(value, "value");
             }
@@ -226,6 +630,44 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_kv_key_length_limit() {
+        let long_key = format!("A{}", "b".repeat(69));
+        let line = format!("{}: value", long_key);
+        let ir = stage2(&s(&[&line]));
+        assert!(matches!(ir[0], IrNode::Paragraph { .. }));
+
+        let config = Config {
+            max_kv_key_len: 80,
+            ..Default::default()
+        };
+        let ir = stage2_with_lines(&s(&[&line]), &[1], &config);
+        match &ir[0] {
+            IrNode::Kv { key, .. } => assert_eq!(key, &long_key),
+            other => panic!("expected kv, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_kv_equals() {
+        let lines = s(&["timeout = 30", "", "x == y", "", "total = a + b", "", "the sum = 4", "", "retries=3;"]);
+        assert!(matches!(stage2(&lines)[0], IrNode::Paragraph { .. }));
+
+        let config = Config {
+            kv_equals: true,
+            ..Default::default()
+        };
+        let ir: Vec<IrNode> = stage2_with_lines(&lines, &[1, 2, 3, 4, 5, 6, 7, 8, 9], &config)
+            .into_iter()
+            .filter(|n| !matches!(n, IrNode::Blank { .. }))
+            .collect();
+        match &ir[0] {
+            IrNode::Kv { key, value, .. } => assert_eq!((key.as_str(), value.as_str()), ("timeout", "30")),
+            other => panic!("expected kv, got {:?}", other),
+        }
+        assert!(ir[1..].iter().all(|n| matches!(n, IrNode::Paragraph { .. })));
+    }
+
     #[test]
     fn test_url_not_kv() {
         let ir = stage2(&s(&["https://example.com: not a kv"]));
@@ -244,7 +686,8 @@ mod tests {
             "| b | 2 |",
         ]));
         match &ir[0] {
-            IrNode::Table { rows } => {
+            IrNode::Table { rows, depth, .. } => {
+                assert_eq!(*depth, 0);
                 assert_eq!(rows.len(), 3);
                 assert_eq!(rows[0], vec!["Name", "Value"]);
                 assert_eq!(rows[1], vec!["a", "1"]);
@@ -253,20 +696,112 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_table_escaped_pipe() {
+        let ir = stage2(&s(&["| Op | Meaning |", "| --- | --- |", "| a \\| b | either |"]));
+        match &ir[0] {
+            IrNode::Table { rows, .. } => assert_eq!(rows[1], vec!["a \\| b", "either"]),
+            _ => panic!("expected table"),
+        }
+    }
+
+    #[test]
+    fn test_indented_table_under_list_item() {
+        let ir = stage2(&s(&[
+            "- Compare options",
+            "",
+            "  | Name | Speed |",
+            "  | --- | --- |",
+            "  | a | fast |",
+            "- Next",
+        ]));
+        match &ir[2] {
+            IrNode::Table { rows, depth, .. } => {
+                assert_eq!(*depth, 1);
+                assert_eq!(rows[1], vec!["a", "fast"]);
+            }
+            other => panic!("expected table, got {:?}", other),
+        }
+        match &ir[3] {
+            IrNode::ListItem { text, .. } => assert_eq!(text, "Next"),
+            _ => panic!("expected list item"),
+        }
+    }
+
+    #[test]
+    fn test_max_nesting_depth() {
+        let lines: Vec<String> = (0..200).map(|d| format!("{}- level {}", "  ".repeat(d), d)).collect();
+        let source_lines: Vec<usize> = (1..=lines.len()).collect();
+        let (ir, diagnostics) = stage2_diagnosed(&lines, &source_lines, &Config::default());
+        assert_eq!(ir.len(), 200);
+        let depths: Vec<usize> = ir
+            .iter()
+            .map(|n| match n {
+                IrNode::ListItem { depth, .. } => *depth,
+                _ => panic!("expected list item"),
+            })
+            .collect();
+        assert_eq!(depths[31], 31);
+        assert!(depths[32..].iter().all(|&d| d == 32));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::DeepNesting);
+        assert!(diagnostics[0].message.contains("line 34: 167 list item(s)"));
+    }
+
+    #[test]
+    fn test_source_lines() {
+        let ir = stage2(&s(&[
+            "# Title",
+            "",
+            "first line",
+            "second line",
+            "",
+            "| A | B |",
+            "| --- | --- |",
+            "| 1 | 2 |",
+        ]));
+        let lines: Vec<usize> = ir.iter().map(IrNode::line).collect();
+        assert_eq!(lines, vec![1, 2, 3, 5, 6]);
+        assert!(matches!(ir[4], IrNode::Table { line: 6, .. }));
+    }
+
+    #[test]
+    fn test_source_lines_after_block() {
+        let ir = stage2_with_lines(&s(&["\u{27E6}BLOCK:0\u{27E7}", "after"]), &[3, 7], &Config::default());
+        assert!(matches!(ir[0], IrNode::BlockRef { line: 3, .. }));
+        assert!(matches!(ir[1], IrNode::Paragraph { line: 7, .. }));
+    }
+
     #[test]
     fn test_block_ref() {
         let ir = stage2(&s(&["\u{27E6}BLOCK:0\u{27E7}"]));
         match &ir[0] {
-            IrNode::BlockRef { index } => assert_eq!(*index, 0),
+            IrNode::BlockRef { index, .. } => assert_eq!(*index, 0),
             _ => panic!("expected block ref"),
         }
     }
 
+    #[test]
+    fn test_prose_admonition() {
+        let lines = s(&["Note: this is not", "a key.", "", "Tip: try it", "Owner: ops"]);
+        assert!(matches!(&stage2(&lines)[0], IrNode::Kv { key, .. } if key == "Note"));
+
+        let config = Config {
+            prose_admonitions: true,
+            ..Default::default()
+        };
+        let source_lines: Vec<usize> = (1..=lines.len()).collect();
+        let ir = stage2_with_lines(&lines, &source_lines, &config);
+        assert!(matches!(&ir[0], IrNode::Admonition { kind, text, .. } if kind == "note" && text == "this is not a key."));
+        assert!(matches!(&ir[2], IrNode::Admonition { kind, text, .. } if kind == "tip" && text == "try it"));
+        assert!(matches!(&ir[3], IrNode::Kv { key, .. } if key == "Owner"));
+    }
+
     #[test]
     fn test_blank() {
         let ir = stage2(&s(&[""]));
         match &ir[0] {
-            IrNode::Blank => {}
+            IrNode::Blank { .. } => {}
             _ => panic!("expected blank"),
         }
     }
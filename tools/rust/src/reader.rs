@@ -0,0 +1,257 @@
+use crate::scope::norm_scope_name;
+use std::ops::Range;
+
+/// One `@scope` section of an LLMD document.
+///
+/// Each body entry is a single line, except code blocks (`::lang`, `<<<`,
+/// content, `>>>`), which are kept together as one `\n`-joined entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LlmdScope {
+    /// Scope name without the `@`; empty for lines before the first scope
+    pub name: String,
+    pub body: Vec<String>,
+}
+
+/// Split LLMD text into scopes, never treating lines inside `<<<`/`>>>` as scopes.
+pub fn read_llmd(text: &str) -> Vec<LlmdScope> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut scopes: Vec<LlmdScope> = Vec::new();
+    let mut current = LlmdScope {
+        name: String::new(),
+        body: Vec::new(),
+    };
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let opens_block = line == "<<<" || (line.starts_with("::") && lines.get(i + 1) == Some(&"<<<"));
+        if opens_block {
+            let start = i;
+            while i < lines.len() && lines[i] != ">>>" {
+                i += 1;
+            }
+            let end = (i + 1).min(lines.len());
+            current.body.push(lines[start..end].join("\n"));
+            i = end;
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('@') {
+            if !current.name.is_empty() || !current.body.is_empty() {
+                scopes.push(current);
+            }
+            current = LlmdScope {
+                name: name.to_string(),
+                body: Vec::new(),
+            };
+        } else if !line.trim().is_empty() {
+            current.body.push(line.to_string());
+        }
+        i += 1;
+    }
+    if !current.name.is_empty() || !current.body.is_empty() {
+        scopes.push(current);
+    }
+    scopes
+}
+
+/// Each scope's name and the 0-based line range it covers in `llmd`, from its
+/// `@` header up to the next scope header. Lines inside `<<<`/`>>>` are never
+/// taken as headers; lines before the first scope belong to no range.
+pub fn scope_ranges(llmd: &str) -> Vec<(String, Range<usize>)> {
+    let mut ranges: Vec<(String, Range<usize>)> = Vec::new();
+    let mut in_block = false;
+    let mut count = 0;
+    for (i, line) in llmd.lines().enumerate() {
+        count = i + 1;
+        if in_block {
+            in_block = line != ">>>";
+        } else if line == "<<<" {
+            in_block = true;
+        } else if let Some(name) = line.strip_prefix('@') {
+            if let Some((_, prev)) = ranges.last_mut() {
+                prev.end = i;
+            }
+            ranges.push((name.to_string(), i..i));
+        }
+    }
+    if let Some((_, last)) = ranges.last_mut() {
+        last.end = count;
+    }
+    ranges
+}
+
+/// Serialize scopes back to LLMD text with a trailing newline.
+pub fn write_llmd(scopes: &[LlmdScope]) -> String {
+    let mut out = String::new();
+    for scope in scopes {
+        if !scope.name.is_empty() {
+            out.push('@');
+            out.push_str(&scope.name);
+            out.push('\n');
+        }
+        for entry in &scope.body {
+            out.push_str(entry);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Keep only the scopes named in `names` (compared after scope-name normalization,
+/// ignoring any `#level` suffix), dropping everything else.
+///
+/// Returns the filtered document and the requested names that matched no scope.
+pub fn filter_scopes(text: &str, names: &[String]) -> (String, Vec<String>) {
+    let scope_key = |s: &LlmdScope| norm_scope_name(s.name.split('#').next().unwrap_or(""), 2);
+    let wanted: Vec<String> = names.iter().map(|n| norm_scope_name(n, 2)).collect();
+    let scopes: Vec<LlmdScope> = read_llmd(text)
+        .into_iter()
+        .filter(|s| !s.name.is_empty() && wanted.contains(&scope_key(s)))
+        .collect();
+    let unknown = names
+        .iter()
+        .zip(&wanted)
+        .filter(|(_, w)| !scopes.iter().any(|s| scope_key(s) == **w))
+        .map(|(n, _)| n.clone())
+        .collect();
+    (write_llmd(&scopes), unknown)
+}
+
+/// Reorder whole scope regions alphabetically by normalized scope name (ignoring
+/// any `#level` suffix). The sort is stable, each region keeps its lines in order,
+/// and lines before the first scope stay at the top.
+pub fn sort_scopes(text: &str) -> String {
+    let scope_key = |s: &LlmdScope| norm_scope_name(s.name.split('#').next().unwrap_or(""), 2);
+    let mut scopes = read_llmd(text);
+    let lead = usize::from(scopes.first().is_some_and(|s| s.name.is_empty()));
+    scopes[lead..].sort_by_cached_key(scope_key);
+    write_llmd(&scopes)
+}
+
+/// Outline `text` for previews: each scope header with only its first body line,
+/// or its leading run of `:` attribute lines. A code block shrinks to its
+/// `::lang` line (`::code` for a bare `<<<`).
+pub fn skeleton(text: &str) -> String {
+    let scopes: Vec<LlmdScope> = read_llmd(text)
+        .into_iter()
+        .map(|s| {
+            let attrs = s.body.iter().take_while(|e| e.starts_with(':') && !e.starts_with("::")).count();
+            let body = match s.body.first() {
+                _ if attrs > 0 => s.body[..attrs].to_vec(),
+                Some(first) if first.contains('\n') => match first.lines().next() {
+                    Some(line) if line.starts_with("::") => vec![line.to_string()],
+                    _ => vec!["::code".to_string()],
+                },
+                Some(first) => vec![first.clone()],
+                None => Vec::new(),
+            };
+            LlmdScope { name: s.name, body }
+        })
+        .collect();
+    write_llmd(&scopes)
+}
+
+/// Parse a `:k=v k2=v2` attribute line into its pairs.
+///
+/// Returns `None` for non-attribute lines and reserved `:_` lines (`:_cols=`,
+/// `:_col=`, `:_pfx=`). A token without `=` continues the previous value.
+pub fn parse_kv_pairs(line: &str) -> Option<Vec<(String, String)>> {
+    let body = line.strip_prefix(':')?;
+    if body.starts_with('_') || body.starts_with(':') {
+        return None;
+    }
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for token in body.split(' ') {
+        match token.split_once('=') {
+            Some((k, v)) if !k.is_empty() => pairs.push((k.to_string(), v.to_string())),
+            _ => {
+                let (_, value) = pairs.last_mut()?;
+                value.push(' ');
+                value.push_str(token);
+            }
+        }
+    }
+    if pairs.is_empty() {
+        None
+    } else {
+        Some(pairs)
+    }
+}
+
+/// Format pairs as a single `:k=v k2=v2` line.
+pub fn format_kv_pairs(pairs: &[(String, String)]) -> String {
+    let joined: Vec<String> = pairs.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    format!(":{}", joined.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_llmd_scopes_and_blocks() {
+        let text = "~note\n@intro\ntext\n::json\n<<<\n@not_a_scope\n>>>\n@auth\n:a=1\n";
+        let scopes = read_llmd(text);
+        assert_eq!(scopes.len(), 3);
+        assert_eq!(scopes[0].name, "");
+        assert_eq!(scopes[0].body, vec!["~note"]);
+        assert_eq!(scopes[1].name, "intro");
+        assert_eq!(scopes[1].body, vec!["text", "::json\n<<<\n@not_a_scope\n>>>"]);
+        assert_eq!(scopes[2].body, vec![":a=1"]);
+        assert_eq!(write_llmd(&scopes), text);
+    }
+
+    #[test]
+    fn test_scope_ranges_skip_blocks() {
+        let text = "~note\n@intro\ntext\n::md\n<<<\n@not_a_scope\n>>>\n@auth\n:a=1\n";
+        assert_eq!(
+            scope_ranges(text),
+            vec![("intro".to_string(), 1..7), ("auth".to_string(), 7..9)]
+        );
+        assert_eq!(scope_ranges("@a\n@b"), vec![("a".to_string(), 0..1), ("b".to_string(), 1..2)]);
+        assert!(scope_ranges("no scopes\n").is_empty());
+    }
+
+    #[test]
+    fn test_filter_scopes() {
+        let text = "@intro\ntext\n@Auth#2\n:a=1\n@limits\n:rps=10\n@auth#2\n-more\n";
+        let names = vec!["auth".to_string(), "Limits".to_string(), "missing".to_string()];
+        let (filtered, unknown) = filter_scopes(text, &names);
+        assert_eq!(filtered, "@Auth#2\n:a=1\n@limits\n:rps=10\n@auth#2\n-more\n");
+        assert_eq!(unknown, vec!["missing"]);
+    }
+
+    #[test]
+    fn test_sort_scopes_keeps_regions_intact() {
+        let text = "~lead\n@Zeta\n:z=1\n-last\n@alpha#2\n::sh\n<<<\n@beta\n>>>\ntext\n@Beta\n-b\n@alpha\n-again\n";
+        assert_eq!(
+            sort_scopes(text),
+            "~lead\n@alpha#2\n::sh\n<<<\n@beta\n>>>\ntext\n@alpha\n-again\n@Beta\n-b\n@Zeta\n:z=1\n-last\n"
+        );
+        assert_eq!(sort_scopes(""), "");
+    }
+
+    #[test]
+    fn test_skeleton() {
+        let text = "~lead\n~more\n@intro\nfirst line\nsecond line\n-item\n@config\n:a=1\n:_cols=k¦v\n-tail\n@setup\n::sh\n<<<\n@x\n>>>\ntext\n@raw\n<<<\ndata\n>>>\n@empty\n";
+        assert_eq!(
+            skeleton(text),
+            "~lead\n@intro\nfirst line\n@config\n:a=1\n:_cols=k¦v\n@setup\n::sh\n@raw\n::code\n@empty\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_kv_pairs() {
+        assert_eq!(
+            parse_kv_pairs(":a=1 b=two words c=3"),
+            Some(vec![
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "two words".to_string()),
+                ("c".to_string(), "3".to_string()),
+            ])
+        );
+        assert_eq!(parse_kv_pairs(":_cols=a¦b"), None);
+        assert_eq!(parse_kv_pairs("::json"), None);
+        assert_eq!(parse_kv_pairs("-item"), None);
+        assert_eq!(format_kv_pairs(&parse_kv_pairs(":a=1 b=2").unwrap()), ":a=1 b=2");
+    }
+}
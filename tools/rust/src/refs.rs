@@ -0,0 +1,115 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+static RE_REF_DEF: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^ {0,3}\[([^\]]+)\]:\s*<?([^\s<>]+)>?(?:\s+(?:"[^"]*"|'[^']*'|\([^)]*\)))?\s*$"#)
+        .unwrap()
+});
+static RE_REF_IMG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"!\[([^\]]*)\]\[([^\]]*)\]").unwrap());
+static RE_REF_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^\]]+)\]\[([^\]]*)\]").unwrap());
+static RE_REF_SHORTCUT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(!?)\[([^\[\]]+)\]").unwrap());
+
+fn norm_label(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Rewrite reference-style links and images (`[text][id]`, `![alt][id]`, and
+/// the shortcut forms `[id]`, `![id]`) to their inline form so later passes
+/// treat them like inline links.
+///
+/// Definition lines become blank to keep line positions stable. An image whose
+/// reference is undefined degrades to its alt text; an undefined link, or a
+/// shortcut naming no definition, is left as written. Expects stage 1 output,
+/// so code block content is never touched.
+pub fn resolve_references(lines: &[String]) -> Vec<String> {
+    let mut defs: HashMap<String, String> = HashMap::new();
+    for line in lines {
+        if let Some(caps) = RE_REF_DEF.captures(line) {
+            defs.entry(norm_label(&caps[1])).or_insert_with(|| caps[2].to_string());
+        }
+    }
+
+    lines
+        .iter()
+        .map(|line| {
+            if RE_REF_DEF.is_match(line) {
+                return String::new();
+            }
+            if !line.contains(']') {
+                return line.clone();
+            }
+            let lookup = |text: &str, id: &str| {
+                let label = if id.trim().is_empty() { text } else { id };
+                defs.get(&norm_label(label))
+            };
+            let line = RE_REF_IMG.replace_all(line, |caps: &regex::Captures| {
+                match lookup(&caps[1], &caps[2]) {
+                    Some(url) => format!("![{}]({})", &caps[1], url),
+                    None => caps[1].to_string(),
+                }
+            });
+            let line = RE_REF_LINK.replace_all(&line, |caps: &regex::Captures| match lookup(&caps[1], &caps[2]) {
+                Some(url) => format!("[{}]({})", &caps[1], url),
+                None => caps[0].to_string(),
+            });
+            // A shortcut is a bracketed label not already part of a link
+            RE_REF_SHORTCUT
+                .replace_all(&line, |caps: &regex::Captures| {
+                    let m = caps.get(0).unwrap();
+                    let linked = line[..m.start()].ends_with(']') || line[m.end()..].starts_with(['(', '[', ':']);
+                    match defs.get(&norm_label(&caps[2])) {
+                        Some(url) if !linked => format!("{}[{}]({})", &caps[1], &caps[2], url),
+                        _ => caps[0].to_string(),
+                    }
+                })
+                .to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(v: &[&str]) -> Vec<String> {
+        v.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn test_reference_image() {
+        let lines = s(&["See ![Diagram][arch] here", "", "[arch]: img/arch.png \"Architecture\""]);
+        let result = resolve_references(&lines);
+        assert_eq!(result, vec!["See ![Diagram](img/arch.png) here", "", ""]);
+    }
+
+    #[test]
+    fn test_collapsed_reference_and_case_insensitive_label() {
+        let lines = s(&["![Logo][] and [Docs][DOCS]", "[logo]: logo.svg", "[docs]: <https://example.com/docs>"]);
+        let result = resolve_references(&lines);
+        assert_eq!(result[0], "![Logo](logo.svg) and [Docs](https://example.com/docs)");
+    }
+
+    #[test]
+    fn test_shortcut_references() {
+        let lines = s(&[
+            "See [Docs] and ![Logo], not [other] or [Docs](x.md)",
+            "[Docs][missing] stays",
+            "[docs]: https://example.com",
+            "[logo]: logo.svg",
+        ]);
+        let result = resolve_references(&lines);
+        assert_eq!(result[0], "See [Docs](https://example.com) and ![Logo](logo.svg), not [other] or [Docs](x.md)");
+        assert_eq!(result[1], "[Docs][missing] stays");
+    }
+
+    #[test]
+    fn test_undefined_references() {
+        let lines = s(&["![Missing][nope] and [text][nope]"]);
+        let result = resolve_references(&lines);
+        assert_eq!(result, vec!["Missing and [text][nope]"]);
+    }
+}
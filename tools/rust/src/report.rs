@@ -0,0 +1,79 @@
+use crate::diag::Diagnostic;
+use serde::Serialize;
+
+/// Version of the `--report` JSON schema, bumped on incompatible changes.
+pub const REPORT_VERSION: u32 = 1;
+
+/// One compiled output and the inputs that went into it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportEntry {
+    /// Input paths (or URLs) in compile order
+    pub inputs: Vec<String>,
+    /// Output path, or `-` for stdout
+    pub output: String,
+    pub raw_tokens: usize,
+    pub compiled_tokens: usize,
+    /// Warnings reported while compiling these inputs, as `{kind, message}`
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Machine-readable summary of one llmdc run, written by `--report`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunReport {
+    pub version: u32,
+    pub compression: i32,
+    pub raw_tokens: usize,
+    pub compiled_tokens: usize,
+    pub entries: Vec<ReportEntry>,
+}
+
+impl RunReport {
+    /// Build a report, totalling token counts over `entries`.
+    pub fn new(compression: i32, entries: Vec<ReportEntry>) -> Self {
+        RunReport {
+            version: REPORT_VERSION,
+            compression,
+            raw_tokens: entries.iter().map(|e| e.raw_tokens).sum(),
+            compiled_tokens: entries.iter().map(|e| e.compiled_tokens).sum(),
+            entries,
+        }
+    }
+}
+
+/// Approximate token count used in summaries and reports.
+pub fn count_tokens(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(input: &str, raw: usize, compiled: usize) -> ReportEntry {
+        ReportEntry {
+            inputs: vec![input.to_string()],
+            output: "-".to_string(),
+            raw_tokens: raw,
+            compiled_tokens: compiled,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_report_totals_and_schema() {
+        let report = RunReport::new(2, vec![entry("a.md", 10, 6), entry("b.md", 5, 3)]);
+        assert_eq!(report.raw_tokens, 15);
+        assert_eq!(report.compiled_tokens, 9);
+        let json = serde_json::to_value(&report).unwrap();
+        let keys: Vec<&String> = json.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["version", "compression", "raw_tokens", "compiled_tokens", "entries"]);
+        assert_eq!(json["entries"][1]["inputs"][0], "b.md");
+        assert_eq!(json["entries"][0]["output"], "-");
+    }
+
+    #[test]
+    fn test_count_tokens() {
+        assert_eq!(count_tokens("@a\n:k=v  text\n"), 3);
+        assert_eq!(count_tokens(""), 0);
+    }
+}
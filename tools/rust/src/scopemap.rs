@@ -0,0 +1,130 @@
+use serde::Serialize;
+
+/// Where an emitted `@scope` line came from.
+#[derive(Debug, Clone)]
+pub struct ScopeOrigin {
+    pub scope: String,
+    /// Source heading text (empty for the injected `@root`)
+    pub heading: String,
+    /// 1-based inclusive source lines from the heading to the end of its section
+    pub source_lines: [usize; 2],
+}
+
+/// One scope map entry. Source and output lines are 1-based and inclusive.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScopeMapEntry {
+    pub scope: String,
+    pub heading: String,
+    pub source_lines: [usize; 2],
+    pub output_lines: [usize; 2],
+}
+
+/// For each emitted line, the index of the scope (in emit order) it falls in:
+/// the `@` lines outside `<<<`/`>>>` blocks open scopes in the order their
+/// origins were recorded. Lines before the first scope belong to none.
+pub fn line_scopes(lines: &[String]) -> Vec<Option<usize>> {
+    let mut current: Option<usize> = None;
+    let mut count = 0;
+    let mut in_block = false;
+    lines
+        .iter()
+        .map(|line| {
+            if in_block || line == "<<<" {
+                in_block = line != ">>>";
+            } else if line.starts_with('@') {
+                current = Some(count);
+                count += 1;
+            }
+            current
+        })
+        .collect()
+}
+
+/// Split compiled `output` lines into scope regions and describe each one.
+///
+/// `scopes[i]` is the index into `origins` of the emitted scope output line `i`
+/// came from, or `None` for a line added after emitting (such as an anchor). A
+/// region starts at each `@` line outside code blocks that came from an emitted
+/// scope, so scopes renamed or merged after emitting keep their entry: `scope`
+/// is the name in the output, `heading` that of the region's first origin, and
+/// `source_lines` spans every origin with lines in the region.
+pub fn build_scope_map(origins: &[ScopeOrigin], output: &[String], scopes: &[Option<usize>]) -> Vec<ScopeMapEntry> {
+    let mut entries: Vec<ScopeMapEntry> = Vec::new();
+    let mut line = 1;
+    let mut in_block = false;
+    for (text, scope) in output.iter().zip(scopes) {
+        if in_block || text == "<<<" {
+            in_block = text != ">>>";
+        } else if let (Some(name), Some(origin)) = (text.strip_prefix('@'), scope.and_then(|k| origins.get(k))) {
+            entries.push(ScopeMapEntry {
+                scope: name.to_string(),
+                heading: origin.heading.clone(),
+                source_lines: origin.source_lines,
+                output_lines: [line, line],
+            });
+        }
+        if let (Some(entry), Some(origin)) = (entries.last_mut(), scope.and_then(|k| origins.get(k))) {
+            entry.source_lines[0] = entry.source_lines[0].min(origin.source_lines[0]);
+            entry.source_lines[1] = entry.source_lines[1].max(origin.source_lines[1]);
+        }
+        line += text.split('\n').count();
+        if let Some(entry) = entries.last_mut() {
+            entry.output_lines[1] = line - 1;
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origin(scope: &str, heading: &str) -> ScopeOrigin {
+        ScopeOrigin {
+            scope: scope.to_string(),
+            heading: heading.to_string(),
+            source_lines: [0, 0],
+        }
+    }
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_build_scope_map_ranges() {
+        let origins = vec![origin("a", "A"), origin("b", "B")];
+        let output = lines("@a\ntext\n@a\n-item\n@b\n:k=v");
+        // The second `@a` is an anchor added after emitting
+        let scopes = vec![Some(0), Some(0), None, Some(0), Some(1), Some(1)];
+        let map = build_scope_map(&origins, &output, &scopes);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map[0].output_lines, [1, 4]);
+        assert_eq!(map[1].heading, "B");
+        assert_eq!(map[1].output_lines, [5, 6]);
+    }
+
+    #[test]
+    fn test_build_scope_map_skips_blocks() {
+        let origins = vec![origin("a", "A"), origin("b", "B")];
+        let output = lines("@a\n::code\n<<<\n@b\n>>>\n@b\ntext");
+        let scopes = line_scopes(&output);
+        assert_eq!(scopes, vec![Some(0), Some(0), Some(0), Some(0), Some(0), Some(1), Some(1)]);
+        let map = build_scope_map(&origins, &output, &scopes);
+        assert_eq!(map[0].output_lines, [1, 5]);
+        assert_eq!(map[1].output_lines, [6, 7]);
+    }
+
+    #[test]
+    fn test_build_scope_map_renamed_scope() {
+        let mut origins = vec![origin("get_a", "get a"), origin("get_b", "get b")];
+        origins[0].source_lines = [3, 5];
+        origins[1].source_lines = [6, 8];
+        let output = lines("@get\n:get_a.k=v\n-get_b: text");
+        let map = build_scope_map(&origins, &output, &[Some(0), Some(0), Some(1)]);
+        assert_eq!(map.len(), 1);
+        assert_eq!((map[0].scope.as_str(), map[0].heading.as_str()), ("get", "get a"));
+        assert_eq!(map[0].output_lines, [1, 3]);
+        assert_eq!(map[0].source_lines, [3, 8]);
+    }
+}
@@ -0,0 +1,97 @@
+/// Receives compiled output line by line, so embedders can route scopes to
+/// separate artifacts without parsing the output string.
+pub trait OutputSink {
+    /// An `@scope` line; `name` is everything after the `@`.
+    fn scope(&mut self, name: &str);
+    /// Any other output line, including each line of a `<<<`/`>>>` block.
+    fn line(&mut self, line: &str);
+    /// Called once after the last line.
+    fn finish(&mut self);
+}
+
+/// Collects output into one string, as returned by [`crate::compile`].
+#[derive(Debug, Default)]
+pub struct StringSink {
+    pub output: String,
+}
+
+impl OutputSink for StringSink {
+    fn scope(&mut self, name: &str) {
+        self.output.push('@');
+        self.output.push_str(name);
+        self.output.push('\n');
+    }
+
+    fn line(&mut self, line: &str) {
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+
+    fn finish(&mut self) {
+        // An empty document is still newline-terminated
+        if self.output.is_empty() {
+            self.output.push('\n');
+        }
+    }
+}
+
+/// Feed stage 6 output to `sink`, splitting multi-line block content into lines.
+/// `@` lines inside blocks are passed on as plain lines.
+pub fn drive(lines: &[String], sink: &mut dyn OutputSink) {
+    let mut in_block = false;
+    for element in lines {
+        for line in element.split('\n') {
+            if line == "<<<" {
+                in_block = true;
+            } else if line == ">>>" {
+                in_block = false;
+            }
+            match line.strip_prefix('@') {
+                Some(name) if !in_block => sink.scope(name),
+                _ => sink.line(line),
+            }
+        }
+    }
+    sink.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct ScopeCollector {
+        scopes: Vec<(String, Vec<String>)>,
+        finished: bool,
+    }
+
+    impl OutputSink for ScopeCollector {
+        fn scope(&mut self, name: &str) {
+            self.scopes.push((name.to_string(), Vec::new()));
+        }
+        fn line(&mut self, line: &str) {
+            self.scopes.last_mut().unwrap().1.push(line.to_string());
+        }
+        fn finish(&mut self) {
+            self.finished = true;
+        }
+    }
+
+    #[test]
+    fn test_drive_routes_scopes_and_block_lines() {
+        let lines: Vec<String> = ["@a", "text", "::md", "<<<", "@not_scope\nx", ">>>", "@b", ":k=v"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut sink = ScopeCollector::default();
+        drive(&lines, &mut sink);
+        assert!(sink.finished);
+        assert_eq!(sink.scopes.len(), 2);
+        assert_eq!(sink.scopes[0].1, vec!["text", "::md", "<<<", "@not_scope", "x", ">>>"]);
+        assert_eq!(sink.scopes[1], ("b".to_string(), vec![":k=v".to_string()]));
+
+        let mut string_sink = StringSink::default();
+        drive(&lines, &mut string_sink);
+        assert_eq!(string_sink.output, lines.join("\n") + "\n");
+    }
+}
@@ -0,0 +1,80 @@
+use crate::blocks::fence_open;
+use regex::Regex;
+use std::sync::LazyLock;
+
+static RE_VAR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}").unwrap());
+
+/// Replace `${VAR}` and `${VAR:-default}` placeholders outside code fences.
+///
+/// Values come from `lookup`, so the caller decides where they live (usually the
+/// process environment). An empty value falls back to the default like in the
+/// shell. Placeholders with no value and no default are left as written and
+/// their names returned, in order of first use.
+pub fn substitute_vars(text: &str, lookup: impl Fn(&str) -> Option<String>) -> (String, Vec<String>) {
+    let mut undefined: Vec<String> = Vec::new();
+    let mut fence: Option<String> = None;
+    let mut out: Vec<String> = Vec::new();
+    for line in text.split('\n') {
+        if let Some(f) = &fence {
+            if line.trim_end() == f {
+                fence = None;
+            }
+            out.push(line.to_string());
+            continue;
+        }
+        if let Some(f) = fence_open(line) {
+            fence = Some(f);
+            out.push(line.to_string());
+            continue;
+        }
+        let replaced = RE_VAR.replace_all(line, |caps: &regex::Captures| {
+            match (lookup(&caps[1]).filter(|v| !v.is_empty()), caps.get(2)) {
+                (Some(value), _) => value,
+                (None, Some(default)) => default.as_str().to_string(),
+                (None, None) => {
+                    if !undefined.iter().any(|u| u == &caps[1]) {
+                        undefined.push(caps[1].to_string());
+                    }
+                    caps[0].to_string()
+                }
+            }
+        });
+        out.push(replaced.to_string());
+    }
+    (out.join("\n"), undefined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => Some("api.example.com".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_substitute_vars_and_defaults() {
+        let (text, undefined) = substitute_vars("Host: ${HOST}\nPort: ${PORT:-8080}\nMode: ${EMPTY:-dev}", lookup);
+        assert_eq!(text, "Host: api.example.com\nPort: 8080\nMode: dev");
+        assert!(undefined.is_empty());
+    }
+
+    #[test]
+    fn test_undefined_vars_are_kept_and_reported() {
+        let (text, undefined) = substitute_vars("${TOKEN} and ${TOKEN} at ${HOST}", lookup);
+        assert_eq!(text, "${TOKEN} and ${TOKEN} at api.example.com");
+        assert_eq!(undefined, vec!["TOKEN"]);
+    }
+
+    #[test]
+    fn test_code_fences_are_skipped() {
+        let text = "${HOST}\n```sh\necho ${HOST}\n```\n${HOST}";
+        let (text, _) = substitute_vars(text, lookup);
+        assert_eq!(text, "api.example.com\n```sh\necho ${HOST}\n```\napi.example.com");
+    }
+}
@@ -0,0 +1,75 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Length in extended grapheme clusters, i.e. user-perceived characters.
+pub fn grapheme_len(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+/// The longest prefix of `s` with at most `max_len` graphemes, never splitting
+/// a cluster (emoji with modifiers, base letters with combining accents).
+pub fn truncate_graphemes(s: &str, max_len: usize) -> &str {
+    match s.grapheme_indices(true).nth(max_len) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
+}
+
+/// `s` cut to at most `max_len` graphemes, `ellipsis` included, when it is longer.
+/// When `ellipsis` alone doesn't fit, `s` is cut without it. Never splits a
+/// character or grapheme cluster, whatever the byte layout.
+pub fn ellipsize_graphemes(s: &str, max_len: usize, ellipsis: &str) -> String {
+    if grapheme_len(s) <= max_len {
+        return s.to_string();
+    }
+    match max_len.checked_sub(grapheme_len(ellipsis)) {
+        Some(keep) => format!("{}{}", truncate_graphemes(s, keep), ellipsis),
+        None => truncate_graphemes(s, max_len).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_keeps_combining_clusters_whole() {
+        // Each "e\u{301}" is two chars but one grapheme
+        let s = "e\u{301}".repeat(5);
+        assert_eq!(grapheme_len(&s), 5);
+        assert_eq!(s.chars().count(), 10);
+        let cut = truncate_graphemes(&s, 3);
+        assert_eq!(cut, "e\u{301}".repeat(3));
+        assert!(!cut.ends_with('e'));
+        assert_eq!(truncate_graphemes(&s, 10), s);
+    }
+
+    #[test]
+    fn test_ellipsize_at_every_boundary() {
+        // 1-, 2-, 3- and 4-byte chars plus a two-char cluster
+        let s = "aé日\u{1F600}e\u{301}b".repeat(3);
+        let len = grapheme_len(&s);
+        for max_len in 0..=len + 1 {
+            for ellipsis in ["", "...", "…"] {
+                let out = ellipsize_graphemes(&s, max_len, ellipsis);
+                assert!(grapheme_len(&out) <= max_len);
+                if max_len >= len {
+                    assert_eq!(out, s);
+                } else if max_len >= grapheme_len(ellipsis) {
+                    assert!(out.ends_with(ellipsis));
+                    assert!(s.starts_with(out.strip_suffix(ellipsis).unwrap()));
+                } else {
+                    assert!(s.starts_with(&out));
+                }
+            }
+        }
+        assert_eq!(ellipsize_graphemes(&s, 4, "…"), "aé日…");
+        assert_eq!(ellipsize_graphemes(&s, 2, "..."), "aé");
+    }
+
+    #[test]
+    fn test_truncate_emoji_with_modifier() {
+        let s = "\u{1F44D}\u{1F3FD}ok";
+        assert_eq!(grapheme_len(s), 3);
+        assert_eq!(truncate_graphemes(s, 1), "\u{1F44D}\u{1F3FD}");
+    }
+}
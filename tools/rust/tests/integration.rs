@@ -43,3 +43,282 @@ fn test_fluentlm_components_parity() {
     let result = llmdc::compile(&input, &config);
     assert_eq!(result, expected);
 }
+
+const SAMPLES: &[&str] = &["api-spec.md", "fluentlm-components.md", "deployment-guide.md"];
+
+fn assert_well_formed(name: &str, level: i32, output: &str) {
+    assert!(
+        output.ends_with('\n') && !output.ends_with("\n\n"),
+        "{} c{}: output must end in exactly one newline",
+        name,
+        level
+    );
+    let lines: Vec<&str> = output.trim_end_matches('\n').split('\n').collect();
+    assert!(
+        lines[0].starts_with('@'),
+        "{} c{}: output must start with an @scope, got {:?}",
+        name,
+        level,
+        lines[0]
+    );
+    let mut in_block = false;
+    for (i, line) in lines.iter().enumerate() {
+        match *line {
+            "<<<" => {
+                assert!(!in_block, "{} c{}: nested <<< at line {}", name, level, i + 1);
+                in_block = true;
+            }
+            ">>>" => {
+                assert!(in_block, "{} c{}: stray >>> at line {}", name, level, i + 1);
+                in_block = false;
+            }
+            _ if !in_block => {
+                assert!(!line.is_empty(), "{} c{}: blank line at {}", name, level, i + 1);
+            }
+            _ => {}
+        }
+    }
+    assert!(!in_block, "{} c{}: unterminated <<< block", name, level);
+}
+
+#[test]
+fn test_samples_all_levels_deterministic_and_well_formed() {
+    let base = load_config();
+    for name in SAMPLES {
+        let input = read_sample(name);
+        for level in 0..=2 {
+            let config = Config {
+                compression: level,
+                ..base.clone()
+            };
+            let first = llmdc::compile(&input, &config);
+            let second = llmdc::compile(&input, &config);
+            assert_eq!(first, second, "{} c{}: compile is not deterministic", name, level);
+            assert_well_formed(name, level, &first);
+            // Re-feeding compiled output is detected and passed through unchanged
+            assert!(
+                llmdc::looks_like_llmd(&first),
+                "{} c{}: compiled output not recognized as LLMD",
+                name,
+                level
+            );
+        }
+    }
+}
+
+#[test]
+fn test_samples_higher_levels_never_longer() {
+    let base = load_config();
+    for name in SAMPLES {
+        let input = read_sample(name);
+        let sizes: Vec<usize> = (0..=2)
+            .map(|level| {
+                let config = Config {
+                    compression: level,
+                    ..base.clone()
+                };
+                llmdc::compile(&input, &config).split_whitespace().count()
+            })
+            .collect();
+        assert!(
+            sizes[0] >= sizes[1] && sizes[1] >= sizes[2],
+            "{}: token counts should not grow with compression level: {:?}",
+            name,
+            sizes
+        );
+    }
+}
+
+#[test]
+fn test_list_files_dedups_overlapping_inputs() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("..")
+        .join("corpora")
+        .join("samples");
+    let once = llmdc::list_files(std::slice::from_ref(&dir)).unwrap();
+    let inputs = vec![dir.join("api-spec.md"), dir.clone(), dir.join(".").join("api-spec.md"), dir.clone()];
+    let files = llmdc::list_files(&inputs).unwrap();
+    assert_eq!(files.len(), once.len());
+    let canonical: Vec<_> = files.iter().map(|f| std::fs::canonicalize(f).unwrap()).collect();
+    assert_eq!(canonical.iter().filter(|f| f.ends_with("api-spec.md")).count(), 1);
+    let mut sorted = files.clone();
+    sorted.sort();
+    assert_eq!(files, sorted);
+}
+
+#[test]
+fn test_compile_file_max_input_bytes() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("..")
+        .join("corpora")
+        .join("samples")
+        .join("api-spec.md");
+    let len = std::fs::metadata(&path).unwrap().len() as usize;
+    let mut config = load_config();
+    config.max_input_bytes = len - 1;
+    let err = llmdc::compile_file(&path, &config).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("over max_input_bytes"));
+
+    config.max_input_bytes = len;
+    assert_eq!(llmdc::compile_file(&path, &config).unwrap(), read_sample("api-spec.llmd"));
+}
+
+#[test]
+fn test_compile_files_with_progress() {
+    let config = load_config();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("..")
+        .join("corpora")
+        .join("samples");
+    let files = vec![dir.join("api-spec.md"), dir.join("fluentlm-components.md")];
+    let mut events: Vec<llmdc::ProgressEvent> = Vec::new();
+    let outputs = llmdc::compile_files_with_progress(&files, &config, |e| events.push(e)).unwrap();
+
+    assert_eq!(outputs[0], read_sample("api-spec.llmd"));
+    assert_eq!(outputs[1], read_sample("fluentlm-components.llmd"));
+    let stages: Vec<(llmdc::ProgressStage, usize)> = events.iter().map(|e| (e.stage, e.index)).collect();
+    assert_eq!(
+        stages,
+        vec![
+            (llmdc::ProgressStage::Started, 0),
+            (llmdc::ProgressStage::Finished, 0),
+            (llmdc::ProgressStage::Started, 1),
+            (llmdc::ProgressStage::Finished, 1),
+        ]
+    );
+    assert!(events.iter().all(|e| e.total == 2));
+    assert_eq!(events[1].path, files[0]);
+    assert_eq!(events[1].tokens, outputs[0].split_whitespace().count());
+    assert_eq!(events[0].tokens, 0);
+
+    let missing = [dir.join("does-not-exist.md")];
+    assert!(llmdc::compile_files_with_progress(&missing, &config, |_| {}).is_err());
+}
+
+#[test]
+fn test_decompile_round_trip() {
+    let mut config = load_config();
+    config.compression = 0;
+    // Scope names with their KV pairs, table rows, list items and code blocks.
+    // KV pairs and table rows compare as cells, so a table that recompiles in
+    // another layout (`:_col=` pairs vs `¦` rows) still matches.
+    let structure = |llmd: &str| {
+        llmdc::reader::read_llmd(llmd)
+            .into_iter()
+            .map(|s| {
+                let mut prefix = String::new();
+                let mut entries: Vec<Vec<String>> = Vec::new();
+                for e in s.body {
+                    if let Some(p) = e.strip_prefix(":_pfx=") {
+                        prefix = p.to_string();
+                    } else if e.starts_with(":_") {
+                        // Table headers and other attributes differ between layouts
+                    } else if let Some(pairs) = e.strip_prefix(':').and_then(|_| llmdc::reader::parse_kv_pairs(&e)) {
+                        for (k, v) in pairs {
+                            let mut cells = vec![format!("{}{}", prefix, k)];
+                            cells.extend(v.split('¦').map(String::from));
+                            entries.push(cells);
+                        }
+                    } else if e.contains('\n') {
+                        // c0 collapses whitespace in recompiled blocks, so a CSV
+                        // table compares by its tokens rather than its rows
+                        entries.push(vec![e.split_whitespace().collect::<Vec<_>>().join(" ")]);
+                    } else if e.starts_with('-') {
+                        entries.push(vec![e]);
+                    } else if e.contains('¦') {
+                        entries.push(e.split('¦').map(String::from).collect());
+                    }
+                }
+                (s.name, entries)
+            })
+            .collect::<Vec<_>>()
+    };
+    let deduped = llmdc::config::Config {
+        dedup_code: true,
+        table_mode: llmdc::config::TableMode::Csv,
+        ..config.clone()
+    };
+    for config in [&config, &deduped] {
+        for name in ["api-spec.md", "deployment-guide.md", "fluentlm-components.md"] {
+            let llmd = llmdc::compile(&read_sample(name), config);
+            let md = llmdc::decompile::decompile(&llmd, config);
+            assert!(md.starts_with("# "), "{}", name);
+            assert_eq!(structure(&llmdc::compile(&md, config)), structure(&llmd), "{}", name);
+        }
+    }
+}
+
+#[test]
+fn test_stdin_name_labels_output() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let dir = std::env::temp_dir().join(format!("llmdc-stdin-name-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let report = dir.join("report.json");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_llmdc"))
+        .args(["-", "--stdin-name", "api.md", "-v", "--report"])
+        .arg(&report)
+        .current_dir(&dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"# Hello\n\nSome text here.\n").unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "@hello\nSome text here\n");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("compiling api.md"));
+    let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(report["entries"][0]["inputs"], serde_json::json!(["api.md"]));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_fail_on_exit_status() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let run = |kind: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_llmdc"))
+            .args(["-", "--fail-on", kind])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"@title\n:key=value\n-item\n").unwrap();
+        child.wait_with_output().unwrap()
+    };
+    // LLMD input is reported as llmd-input: a non-listed kind warns but doesn't fail
+    let out = run("scope-order");
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("already looks like LLMD"));
+    let out = run("llmd-input");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("matched --fail-on"));
+}
+
+#[test]
+fn test_stdin_limit_cuts_multibyte_char() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_llmdc"))
+        .args(["-", "--max-input-bytes", "7"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The limit cuts the input inside the first "…"
+    child.stdin.take().unwrap().write_all("# T\n\né…é…".as_bytes()).unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("over max_input_bytes (7)"));
+}